@@ -0,0 +1,98 @@
+//! CPU used by the UI loop while nothing happens, with the old fixed
+//! 16ms tick and with frames drawn on demand.
+//!
+//! Run with `cargo bench --bench idle_cpu`
+
+use std::time::Duration;
+
+use bltui::{
+    events::{
+        tick::{spawn_frame_scheduler, spawn_refresh_timer},
+        AppEvent,
+    },
+    ui::draw_frame,
+    App,
+};
+use tui::{backend::TestBackend, Terminal};
+
+static IDLE_TIME: Duration = Duration::from_secs(5);
+static OLD_TICK_RATE: Duration = Duration::from_millis(16);
+static LOG_REFRESH_RATE: Duration = Duration::from_secs(1);
+
+/// User and system CPU time of this process, from /proc/self/stat
+fn cpu_time() -> Duration {
+    let stat = std::fs::read_to_string("/proc/self/stat").expect("/proc/self/stat is readable");
+    // The command name may contain spaces, the fields start after it
+    let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..]
+        .split_whitespace()
+        .collect();
+    let ticks: u64 = fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap();
+    // USER_HZ is 100 on every Linux platform
+    Duration::from_millis(ticks * 10)
+}
+
+/// Draw a frame on every tick received during `IDLE_TIME`, returning the
+/// number of frames and the CPU time used
+async fn run_idle(mut app: App) -> (u32, Duration) {
+    let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+    let mut frames = 0;
+    let start = cpu_time();
+
+    let deadline = tokio::time::sleep(IDLE_TIME);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            Some(AppEvent::Tick) = app.events() => {
+                draw_frame(&mut terminal, &mut app, false).await;
+                frames += 1;
+            }
+        }
+    }
+
+    (frames, cpu_time() - start)
+}
+
+async fn fixed_tick() -> (u32, Duration) {
+    let app = App::new();
+    let tx = app.tx();
+    let ticker = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(OLD_TICK_RATE);
+        loop {
+            interval.tick().await;
+            if tx.send(AppEvent::Tick).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let result = run_idle(app).await;
+    ticker.abort();
+    result
+}
+
+async fn on_demand() -> (u32, Duration) {
+    let app = App::new();
+    let frames = spawn_frame_scheduler(30, app.tx());
+    frames.request();
+    spawn_refresh_timer(LOG_REFRESH_RATE, frames);
+
+    run_idle(app).await
+}
+
+#[tokio::main]
+async fn main() {
+    for (name, (frames, cpu)) in [
+        ("fixed 16ms tick", fixed_tick().await),
+        ("on demand", on_demand().await),
+    ] {
+        println!(
+            "{:<16} {:>4} frames in {:?}, {:>4}ms of CPU ({:.1}%)",
+            name,
+            frames,
+            IDLE_TIME,
+            cpu.as_millis(),
+            cpu.as_secs_f64() / IDLE_TIME.as_secs_f64() * 100.0
+        );
+    }
+}
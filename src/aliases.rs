@@ -0,0 +1,103 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use log::debug;
+use toml_edit::value;
+
+use crate::{
+    bluetooth::devices::Device,
+    settings::{config_table, edit_config_file},
+    Error,
+};
+
+/// Local device aliases, from the `[aliases]` table of `config.toml`.
+/// Used when BlueZ can't store the alias itself
+#[derive(Debug, Default)]
+pub struct Aliases {
+    config_file: Option<PathBuf>,
+    aliases: HashMap<String, String>,
+}
+
+impl Aliases {
+    pub fn new(aliases: HashMap<String, String>, config_file: PathBuf) -> Aliases {
+        Aliases {
+            config_file: Some(config_file),
+            aliases: aliases
+                .into_iter()
+                .map(|(address, alias)| (address.to_uppercase(), alias))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, address: &str) -> Option<&String> {
+        self.aliases.get(&address.to_uppercase())
+    }
+
+    /// Set the local alias of a device if it has one
+    pub fn apply(&self, mut device: Device) -> Device {
+        if let Some(alias) = self.get(&device.address) {
+            device.alias = Some(alias.clone());
+        }
+        device
+    }
+
+    /// Set or, with an empty alias, remove a local alias. The config
+    /// file is edited in place to keep the user's formatting
+    pub fn set(&mut self, address: &str, alias: &str) -> Result<(), Error> {
+        let address = address.to_uppercase();
+        if alias.is_empty() {
+            self.aliases.remove(&address);
+        } else {
+            self.aliases.insert(address.clone(), alias.to_string());
+        }
+
+        let Some(config_file) = &self.config_file else {
+            return Ok(());
+        };
+
+        edit_config_file(config_file, |document| {
+            let aliases = config_table(document, "aliases")?;
+            if alias.is_empty() {
+                aliases.remove(&address);
+            } else {
+                aliases[&address] = value(alias);
+            }
+            Some(())
+        })?;
+        debug!("Saved alias of {} to {:?}", address, config_file);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_set_alias_keeps_config() {
+        let mut folder = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        folder.push("temp");
+        folder.push(format!(
+            "aliases-{}",
+            rand::thread_rng().gen_range(0..100000)
+        ));
+        std::fs::create_dir_all(&folder).unwrap();
+        let config_file = folder.join("config.toml");
+        std::fs::write(&config_file, "# My adapter\nadapter = \"hci0\"\n").unwrap();
+
+        let mut aliases = Aliases::new(HashMap::new(), config_file.clone());
+        aliases.set("aa:bb:cc:dd:ee:ff", "Headset").unwrap();
+        aliases.set("11:22:33:44:55:66", "Phone").unwrap();
+        aliases.set("11:22:33:44:55:66", "").unwrap();
+
+        let content = std::fs::read_to_string(&config_file).unwrap();
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        assert!(content.starts_with("# My adapter\nadapter = \"hci0\"\n"));
+        assert!(content.contains("[aliases]\n\"AA:BB:CC:DD:EE:FF\" = \"Headset\"\n"));
+        assert!(!content.contains("Phone"));
+        assert_eq!(aliases.get("AA:BB:CC:DD:EE:FF").unwrap(), "Headset");
+        assert!(aliases.get("11:22:33:44:55:66").is_none());
+    }
+}
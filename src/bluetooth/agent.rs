@@ -9,7 +9,10 @@ use tokio::{
 };
 use zbus::{dbus_interface, Connection};
 
-use crate::events::{agent::AgentEvent, AppEvent};
+use crate::{
+    events::{agent::AgentEvent, AppEvent},
+    Error,
+};
 use log::debug;
 use zbus::DBusError;
 
@@ -24,17 +27,36 @@ pub enum BluezError {
 
 struct AgentServer {
     tx: Sender<AppEvent>,
+    /// Time the app has to answer a request
+    timeout: Duration,
+}
+
+impl AgentServer {
+    /// Send the request to the app and wait for its answer. Requests
+    /// that can't be answered are cancelled
+    async fn forward<T>(
+        &self,
+        event: AgentEvent,
+        rx: oneshot::Receiver<Result<T, BluezError>>,
+    ) -> Result<T, BluezError> {
+        self.tx
+            .send(AppEvent::Agent(event))
+            .await
+            .map_err(|_| BluezError::Canceled("bltui is shutting down".to_string()))?;
+
+        match timeout(self.timeout, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(BluezError::Canceled("request was dropped".to_string())),
+            Err(_) => Err(BluezError::Canceled("no answer in time".to_string())),
+        }
+    }
 }
 
 #[dbus_interface(name = "org.bluez.Agent1")]
 impl AgentServer {
     async fn release(&self) -> Result<(), BluezError> {
         let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(AppEvent::Agent(AgentEvent::Release { tx }))
-            .await
-            .unwrap();
-        timeout(TIMEOUT, rx).await.unwrap().unwrap()
+        self.forward(AgentEvent::Release { tx }, rx).await
     }
 
     async fn request_pin_code(
@@ -42,11 +64,7 @@ impl AgentServer {
         _device: zvariant::ObjectPath<'_>,
     ) -> Result<String, BluezError> {
         let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(AppEvent::Agent(AgentEvent::RequestPincode { tx }))
-            .await
-            .unwrap();
-        timeout(TIMEOUT, rx).await.unwrap().unwrap()
+        self.forward(AgentEvent::RequestPincode { tx }, rx).await
     }
 
     async fn display_pin_code(
@@ -55,20 +73,13 @@ impl AgentServer {
         pincode: String,
     ) -> Result<(), BluezError> {
         let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(AppEvent::Agent(AgentEvent::DisplayPincode { pincode, tx }))
+        self.forward(AgentEvent::DisplayPincode { pincode, tx }, rx)
             .await
-            .unwrap();
-        timeout(TIMEOUT, rx).await.unwrap().unwrap()
     }
 
     async fn request_passkey(&self, _device: zvariant::ObjectPath<'_>) -> Result<u32, BluezError> {
         let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(AppEvent::Agent(AgentEvent::RequestPasskey { tx }))
-            .await
-            .unwrap();
-        timeout(TIMEOUT, rx).await.unwrap().unwrap()
+        self.forward(AgentEvent::RequestPasskey { tx }, rx).await
     }
 
     async fn display_passkey(
@@ -78,11 +89,8 @@ impl AgentServer {
         _entered: u16,
     ) -> Result<(), BluezError> {
         let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(AppEvent::Agent(AgentEvent::DisplayPasskey { passkey, tx }))
+        self.forward(AgentEvent::DisplayPasskey { passkey, tx }, rx)
             .await
-            .unwrap();
-        timeout(TIMEOUT, rx).await.unwrap().unwrap()
     }
 
     async fn request_confirmation(
@@ -91,15 +99,10 @@ impl AgentServer {
         passkey: u32,
     ) -> Result<(), BluezError> {
         let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(AppEvent::Agent(AgentEvent::RequestConfirmation {
-                passkey,
-                tx,
-            }))
-            .await
-            .unwrap();
-        debug!("Sent request for confirmation");
-        let result = timeout(TIMEOUT, rx).await.unwrap().unwrap();
+        debug!("Sending request for confirmation");
+        let result = self
+            .forward(AgentEvent::RequestConfirmation { passkey, tx }, rx)
+            .await;
         debug!("Received request for confirmation input");
         result
     }
@@ -109,11 +112,8 @@ impl AgentServer {
         _device: zvariant::ObjectPath<'_>,
     ) -> Result<(), BluezError> {
         let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(AppEvent::Agent(AgentEvent::RequestAuthorization { tx }))
+        self.forward(AgentEvent::RequestAuthorization { tx }, rx)
             .await
-            .unwrap();
-        timeout(TIMEOUT, rx).await.unwrap().unwrap()
     }
 
     async fn authorize_service(
@@ -122,20 +122,13 @@ impl AgentServer {
         uuid: String,
     ) -> Result<(), BluezError> {
         let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(AppEvent::Agent(AgentEvent::AuthorizeService { uuid, tx }))
+        self.forward(AgentEvent::AuthorizeService { uuid, tx }, rx)
             .await
-            .unwrap();
-        timeout(TIMEOUT, rx).await.unwrap().unwrap()
     }
 
     async fn cancel(&self) -> Result<(), BluezError> {
         let (tx, rx) = oneshot::channel();
-        self.tx
-            .send(AppEvent::Agent(AgentEvent::Cancel { tx }))
-            .await
-            .unwrap();
-        timeout(TIMEOUT, rx).await.unwrap().unwrap()
+        self.forward(AgentEvent::Cancel { tx }, rx).await
     }
 }
 
@@ -158,27 +151,42 @@ pub struct Agent<'a> {
     path: zvariant::ObjectPath<'a>,
     capability: AgentCapability,
     connection: Connection,
+    timeout: Duration,
 }
 
 impl Agent<'static> {
+    /// Agent on the system bus, where BlueZ is
     pub async fn initialize_dbus_connection(
         path: String,
         capability: AgentCapability,
-    ) -> Agent<'static> {
-        let connection = Connection::system().await.unwrap();
+    ) -> Result<Agent<'static>, Error> {
+        Agent::new(Connection::system().await?, path, capability)
+    }
 
-        Agent {
-            path: zvariant::ObjectPath::try_from(path).unwrap(),
+    /// Agent served at `path` on the bus of `connection`
+    pub fn new(
+        connection: Connection,
+        path: String,
+        capability: AgentCapability,
+    ) -> Result<Agent<'static>, Error> {
+        Ok(Agent {
+            path: zvariant::ObjectPath::try_from(path).map_err(zbus::Error::from)?,
             capability,
             connection,
-        }
+            timeout: TIMEOUT,
+        })
+    }
+
+    pub fn connection(&self) -> Connection {
+        self.connection.clone()
     }
 
-    pub async fn request_name(&self, name: &str) {
-        self.connection.request_name(name).await.unwrap();
+    pub async fn request_name(&self, name: &str) -> Result<(), Error> {
+        self.connection.request_name(name).await?;
+        Ok(())
     }
 
-    pub async fn register(&self) {
+    pub async fn register(&self) -> Result<(), Error> {
         self.connection
             .call_method(
                 Some("org.bluez"),
@@ -187,13 +195,13 @@ impl Agent<'static> {
                 "RegisterAgent",
                 &(self.path.clone(), self.capability.to_string()),
             )
-            .await
-            .unwrap();
+            .await?;
 
         debug!("Registered the agent");
+        Ok(())
     }
 
-    pub async fn request_default(&self) {
+    pub async fn request_default(&self) -> Result<(), Error> {
         self.connection
             .call_method(
                 Some("org.bluez"),
@@ -202,13 +210,13 @@ impl Agent<'static> {
                 "RequestDefaultAgent",
                 &(self.path.clone(),),
             )
-            .await
-            .unwrap();
+            .await?;
 
         debug!("Requested default agent");
+        Ok(())
     }
 
-    pub async fn unregister(&self) {
+    pub async fn unregister(&self) -> Result<(), Error> {
         self.connection
             .call_method(
                 Some("org.bluez"),
@@ -217,19 +225,388 @@ impl Agent<'static> {
                 "UnregisterAgent",
                 &(self.path.clone(),),
             )
-            .await
-            .unwrap();
+            .await?;
 
         debug!("Unregistered the agent");
+        Ok(())
     }
 
-    pub async fn start_server(&self, tx: Sender<AppEvent>) {
+    pub async fn start_server(&self, tx: Sender<AppEvent>) -> Result<(), Error> {
         self.connection
             .object_server()
-            .at("/bltui/agent", AgentServer { tx })
+            .at(
+                &self.path,
+                AgentServer {
+                    tx,
+                    timeout: self.timeout,
+                },
+            )
+            .await?;
+
+        debug!("Started the agent server");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth::private_bus::PrivateBus;
+    use std::sync::{Arc, Mutex};
+    use tokio::{sync::mpsc::Receiver, task::JoinHandle};
+    use zvariant::ObjectPath;
+
+    /// `org.bluez.AgentManager1` of BlueZ, remembering the calls
+    struct FakeAgentManager {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[dbus_interface(name = "org.bluez.AgentManager1")]
+    impl FakeAgentManager {
+        fn register_agent(&self, agent: ObjectPath<'_>, capability: String) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("RegisterAgent {} {}", agent, capability));
+        }
+
+        fn request_default_agent(&self, agent: ObjectPath<'_>) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("RequestDefaultAgent {}", agent));
+        }
+
+        fn unregister_agent(&self, agent: ObjectPath<'_>) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("UnregisterAgent {}", agent));
+        }
+    }
+
+    async fn start_bluez(bus: &PrivateBus) -> (Connection, Arc<Mutex<Vec<String>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let connection = bus.connection().await;
+        connection
+            .object_server()
+            .at(
+                "/org/bluez",
+                FakeAgentManager {
+                    calls: calls.clone(),
+                },
+            )
             .await
             .unwrap();
+        connection.request_name("org.bluez").await.unwrap();
+        (connection, calls)
+    }
+
+    async fn start_agent(
+        bus: &PrivateBus,
+        timeout: Duration,
+    ) -> (Agent<'static>, Receiver<AppEvent>) {
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let mut agent = Agent::new(
+            bus.connection().await,
+            "/bltui/agent".into(),
+            AgentCapability::KeyboardDisplay,
+        )
+        .unwrap();
+        agent.timeout = timeout;
+        agent.start_server(tx).await.unwrap();
+        (agent, rx)
+    }
+
+    fn device() -> ObjectPath<'static> {
+        ObjectPath::try_from("/org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF").unwrap()
+    }
+
+    /// Call `method` of the agent the way BlueZ does, returning the
+    /// event it produced and the pending reply
+    async fn request(
+        bluez: &Connection,
+        agent: &Agent<'static>,
+        rx: &mut Receiver<AppEvent>,
+        method: &'static str,
+        body: impl serde::Serialize + zvariant::DynamicType + Send + Sync + 'static,
+    ) -> (AgentEvent, JoinHandle<zbus::Result<Arc<zbus::Message>>>) {
+        let bluez = bluez.clone();
+        let destination = agent.connection.unique_name().unwrap().to_owned();
+        let reply = tokio::spawn(async move {
+            bluez
+                .call_method(
+                    Some(destination),
+                    "/bltui/agent",
+                    Some("org.bluez.Agent1"),
+                    method,
+                    &body,
+                )
+                .await
+        });
+
+        match rx.recv().await {
+            Some(AppEvent::Agent(event)) => (event, reply),
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+    }
+
+    fn error(reply: zbus::Result<Arc<zbus::Message>>) -> (String, Option<String>) {
+        match reply {
+            Err(zbus::Error::MethodError(name, message, _)) => (name.to_string(), message),
+            res => panic!("Unexpected reply {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_with_agent_manager() {
+        let bus = PrivateBus::new();
+        let (_bluez, calls) = start_bluez(&bus).await;
+        let (agent, _rx) = start_agent(&bus, TIMEOUT).await;
+
+        agent.register().await.unwrap();
+        agent.request_default().await.unwrap();
+        agent.unregister().await.unwrap();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "RegisterAgent /bltui/agent KeyboardDisplay",
+                "RequestDefaultAgent /bltui/agent",
+                "UnregisterAgent /bltui/agent",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pincode_and_passkey_are_answered() {
+        let bus = PrivateBus::new();
+        let (bluez, _) = start_bluez(&bus).await;
+        let (agent, mut rx) = start_agent(&bus, TIMEOUT).await;
+
+        let (event, reply) = request(&bluez, &agent, &mut rx, "RequestPinCode", (device(),)).await;
+        match event {
+            AgentEvent::RequestPincode { tx } => tx.send(Ok("1234".to_string())).unwrap(),
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+        let reply = reply.await.unwrap().unwrap();
+        assert_eq!(reply.body::<String>().unwrap(), "1234");
+
+        let (event, reply) = request(&bluez, &agent, &mut rx, "RequestPasskey", (device(),)).await;
+        match event {
+            AgentEvent::RequestPasskey { tx } => tx.send(Ok(123456)).unwrap(),
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+        let reply = reply.await.unwrap().unwrap();
+        assert_eq!(reply.body::<u32>().unwrap(), 123456);
+    }
+
+    #[tokio::test]
+    async fn test_display_requests() {
+        let bus = PrivateBus::new();
+        let (bluez, _) = start_bluez(&bus).await;
+        let (agent, mut rx) = start_agent(&bus, TIMEOUT).await;
+
+        let (event, reply) = request(
+            &bluez,
+            &agent,
+            &mut rx,
+            "DisplayPinCode",
+            (device(), "0000".to_string()),
+        )
+        .await;
+        match event {
+            AgentEvent::DisplayPincode { pincode, tx } => {
+                assert_eq!(pincode, "0000");
+                tx.send(Ok(())).unwrap();
+            }
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+        assert!(reply.await.unwrap().is_ok());
+
+        let (event, reply) = request(
+            &bluez,
+            &agent,
+            &mut rx,
+            "DisplayPasskey",
+            (device(), 123456_u32, 2_u16),
+        )
+        .await;
+        match event {
+            AgentEvent::DisplayPasskey { passkey, tx } => {
+                assert_eq!(passkey, 123456);
+                tx.send(Ok(())).unwrap();
+            }
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+        assert!(reply.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_confirmation_and_authorization() {
+        let bus = PrivateBus::new();
+        let (bluez, _) = start_bluez(&bus).await;
+        let (agent, mut rx) = start_agent(&bus, TIMEOUT).await;
+
+        let (event, reply) = request(
+            &bluez,
+            &agent,
+            &mut rx,
+            "RequestConfirmation",
+            (device(), 654321_u32),
+        )
+        .await;
+        match event {
+            AgentEvent::RequestConfirmation { passkey, tx } => {
+                assert_eq!(passkey, 654321);
+                tx.send(Ok(())).unwrap();
+            }
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+        assert!(reply.await.unwrap().is_ok());
+
+        let (event, reply) =
+            request(&bluez, &agent, &mut rx, "RequestAuthorization", (device(),)).await;
+        match event {
+            AgentEvent::RequestAuthorization { tx } => tx.send(Ok(())).unwrap(),
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+        assert!(reply.await.unwrap().is_ok());
+
+        let uuid = "0000110b-0000-1000-8000-00805f9b34fb";
+        let (event, reply) = request(
+            &bluez,
+            &agent,
+            &mut rx,
+            "AuthorizeService",
+            (device(), uuid.to_string()),
+        )
+        .await;
+        match event {
+            AgentEvent::AuthorizeService {
+                uuid: requested,
+                tx,
+            } => {
+                assert_eq!(requested, uuid);
+                tx.send(Ok(())).unwrap();
+            }
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+        assert!(reply.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_release_and_cancel() {
+        let bus = PrivateBus::new();
+        let (bluez, _) = start_bluez(&bus).await;
+        let (agent, mut rx) = start_agent(&bus, TIMEOUT).await;
+
+        let (event, reply) = request(&bluez, &agent, &mut rx, "Cancel", ()).await;
+        match event {
+            AgentEvent::Cancel { tx } => tx.send(Ok(())).unwrap(),
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+        assert!(reply.await.unwrap().is_ok());
+
+        let (event, reply) = request(&bluez, &agent, &mut rx, "Release", ()).await;
+        match event {
+            AgentEvent::Release { tx } => tx.send(Ok(())).unwrap(),
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+        assert!(reply.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_request() {
+        let bus = PrivateBus::new();
+        let (bluez, _) = start_bluez(&bus).await;
+        let (agent, mut rx) = start_agent(&bus, TIMEOUT).await;
+
+        let (event, reply) = request(
+            &bluez,
+            &agent,
+            &mut rx,
+            "RequestConfirmation",
+            (device(), 654321_u32),
+        )
+        .await;
+        match event {
+            AgentEvent::RequestConfirmation { tx, .. } => tx
+                .send(Err(BluezError::Rejected("rejected".to_string())))
+                .unwrap(),
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+
+        assert_eq!(
+            error(reply.await.unwrap()),
+            (
+                "org.bluez.Error.Rejected".to_string(),
+                Some("rejected".to_string())
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_is_cancelled() {
+        let bus = PrivateBus::new();
+        let (bluez, _) = start_bluez(&bus).await;
+        let (agent, mut rx) = start_agent(&bus, TIMEOUT).await;
+
+        let (event, reply) =
+            request(&bluez, &agent, &mut rx, "RequestAuthorization", (device(),)).await;
+        drop(event);
+
+        assert_eq!(
+            error(reply.await.unwrap()),
+            (
+                "org.bluez.Error.Canceled".to_string(),
+                Some("request was dropped".to_string())
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_request_times_out() {
+        let bus = PrivateBus::new();
+        let (bluez, _) = start_bluez(&bus).await;
+        let (agent, mut rx) = start_agent(&bus, Duration::from_millis(100)).await;
+
+        // The answer is kept but never sent
+        let (_event, reply) = request(&bluez, &agent, &mut rx, "RequestPasskey", (device(),)).await;
 
-        debug!("Started the agent server")
+        assert_eq!(
+            error(reply.await.unwrap()),
+            (
+                "org.bluez.Error.Canceled".to_string(),
+                Some("no answer in time".to_string())
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_closed_app_cancels() {
+        let bus = PrivateBus::new();
+        let (bluez, _) = start_bluez(&bus).await;
+        let (agent, rx) = start_agent(&bus, TIMEOUT).await;
+        drop(rx);
+
+        let destination = agent.connection.unique_name().unwrap().to_owned();
+        let reply = bluez
+            .call_method(
+                Some(destination),
+                "/bltui/agent",
+                Some("org.bluez.Agent1"),
+                "RequestPinCode",
+                &(device(),),
+            )
+            .await;
+
+        assert_eq!(
+            error(reply),
+            (
+                "org.bluez.Error.Canceled".to_string(),
+                Some("bltui is shutting down".to_string())
+            )
+        );
     }
 }
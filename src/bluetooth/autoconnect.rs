@@ -0,0 +1,443 @@
+use std::{
+    cmp::min,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::debug;
+use time::{macros::format_description, OffsetDateTime};
+use tokio::task::JoinHandle;
+use toml_edit::{value, Array};
+
+use crate::{settings::edit_config_file, Error};
+
+static INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+static MAX_BACKOFF: Duration = Duration::from_secs(300);
+static HISTORY_LENGTH: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    pub at: OffsetDateTime,
+    pub error: Option<String>,
+}
+
+impl Attempt {
+    pub fn time(&self) -> String {
+        self.at
+            .format(format_description!("[hour]:[minute]:[second]"))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+struct ReconnectState {
+    paused: bool,
+    /// An attempt is scheduled, or its connection is running
+    pending: bool,
+    backoff: Duration,
+    history: Vec<Attempt>,
+}
+
+impl ReconnectState {
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.backoff;
+        self.backoff = min(self.backoff * 2, MAX_BACKOFF);
+        self.pending = true;
+        delay
+    }
+}
+
+impl Default for ReconnectState {
+    fn default() -> ReconnectState {
+        ReconnectState {
+            paused: false,
+            pending: false,
+            backoff: INITIAL_BACKOFF,
+            history: Vec::new(),
+        }
+    }
+}
+
+/// Addresses of the favourite devices, upper case. Clones share the
+/// same set, so the device list follows the favourites toggled here
+#[derive(Debug, Clone, Default)]
+pub struct Favourites(Arc<Mutex<HashSet<String>>>);
+
+impl Favourites {
+    pub fn contains(&self, address: &str) -> bool {
+        self.0
+            .lock()
+            .is_ok_and(|favourites| favourites.contains(&address.to_uppercase()))
+    }
+
+    /// Returns whether the address was a favourite
+    fn set(&self, address: &str, favourite: bool) -> bool {
+        let Ok(mut favourites) = self.0.lock() else {
+            return false;
+        };
+        if favourite {
+            !favourites.insert(address.to_uppercase())
+        } else {
+            favourites.remove(&address.to_uppercase())
+        }
+    }
+}
+
+/// Keeps track of favourite devices and decides when to (re)connect
+/// to them. Every `on_*` method returns the delay after which a
+/// connection attempt should be made, if any
+#[derive(Debug, Default)]
+pub struct AutoConnect {
+    /// Where favourites are saved when they are toggled
+    config_file: Option<PathBuf>,
+    favourites: Favourites,
+    states: HashMap<String, ReconnectState>,
+    /// Scheduled connection attempts, by address
+    attempts: HashMap<String, JoinHandle<()>>,
+}
+
+impl AutoConnect {
+    pub fn new(favourites: Vec<String>, config_file: Option<PathBuf>) -> AutoConnect {
+        AutoConnect {
+            config_file,
+            favourites: Favourites(Arc::new(Mutex::new(
+                favourites
+                    .into_iter()
+                    .map(|address| address.to_uppercase())
+                    .collect(),
+            ))),
+            states: HashMap::new(),
+            attempts: HashMap::new(),
+        }
+    }
+
+    pub fn is_favourite(&self, address: &str) -> bool {
+        self.favourites.contains(address)
+    }
+
+    /// The favourites, which follow the ones toggled afterwards
+    pub fn favourites(&self) -> Favourites {
+        self.favourites.clone()
+    }
+
+    /// Returns whether the device is now a favourite. The config file
+    /// is edited in place to keep the user's formatting
+    pub fn toggle_favourite(&mut self, address: &str) -> Result<bool, Error> {
+        let address = address.to_uppercase();
+        let favourite = if self.favourites.set(&address, false) {
+            self.cancel_attempt(&address);
+            self.states.remove(&address);
+            false
+        } else {
+            self.favourites.set(&address, true);
+            true
+        };
+
+        let Some(config_file) = &self.config_file else {
+            return Ok(favourite);
+        };
+
+        edit_config_file(config_file, |document| {
+            let favourites = document
+                .entry("favourites")
+                .or_insert(value(Array::new()))
+                .as_array_mut()?;
+            favourites.retain(|saved| {
+                !saved
+                    .as_str()
+                    .is_some_and(|saved| saved.eq_ignore_ascii_case(&address))
+            });
+            if favourite {
+                favourites.push(address.as_str());
+            }
+            favourites.fmt();
+            Some(())
+        })?;
+        debug!("Saved the favourites to {:?}", config_file);
+
+        Ok(favourite)
+    }
+
+    /// Keep the task of a scheduled connection attempt, to abort it if
+    /// the device stops being reconnected to
+    pub fn track_attempt(&mut self, address: &str, task: JoinHandle<()>) {
+        if let Some(previous) = self.attempts.insert(address.to_uppercase(), task) {
+            previous.abort();
+        }
+    }
+
+    /// The scheduled attempt is due, its connection is started as an
+    /// operation and reported with `on_attempt`
+    pub fn on_due(&mut self, address: &str) {
+        self.attempts.remove(&address.to_uppercase());
+    }
+
+    /// Whether the outcome of a connection to `address` is waited for
+    pub fn is_attempting(&self, address: &str) -> bool {
+        self.states
+            .get(&address.to_uppercase())
+            .is_some_and(|state| state.pending)
+    }
+
+    fn cancel_attempt(&mut self, address: &str) {
+        if let Some(task) = self.attempts.remove(address) {
+            task.abort();
+        }
+        if let Some(state) = self.states.get_mut(address) {
+            state.pending = false;
+        }
+    }
+
+    fn schedulable_state(&mut self, address: &str) -> Option<&mut ReconnectState> {
+        let address = address.to_uppercase();
+        if !self.favourites.contains(&address) {
+            return None;
+        }
+        let state = self.states.entry(address).or_default();
+        if state.paused || state.pending {
+            None
+        } else {
+            Some(state)
+        }
+    }
+
+    pub fn on_discovered(&mut self, address: &str, connected: bool) -> Option<Duration> {
+        if connected {
+            return None;
+        }
+        let state = self.schedulable_state(address)?;
+        state.pending = true;
+        Some(Duration::ZERO)
+    }
+
+    pub fn on_disconnected(&mut self, address: &str) -> Option<Duration> {
+        let state = self.schedulable_state(address)?;
+        Some(state.next_delay())
+    }
+
+    pub fn on_attempt(&mut self, address: &str, error: Option<String>) -> Option<Duration> {
+        // A connection started by the user may end before the attempt
+        if let Some(task) = self.attempts.remove(&address.to_uppercase()) {
+            task.abort();
+        }
+        let state = self.states.get_mut(&address.to_uppercase())?;
+        state.pending = false;
+
+        let failed = error.is_some();
+        state.history.push(Attempt {
+            at: OffsetDateTime::now_utc(),
+            error,
+        });
+        if state.history.len() > HISTORY_LENGTH {
+            state.history.remove(0);
+        }
+
+        if !failed {
+            state.backoff = INITIAL_BACKOFF;
+            return None;
+        }
+
+        if state.paused {
+            None
+        } else {
+            Some(state.next_delay())
+        }
+    }
+
+    /// A manual disconnection stops reconnecting until the next
+    /// manual connection, and cancels the scheduled attempt
+    pub fn pause(&mut self, address: &str) {
+        if self.is_favourite(address) {
+            let address = address.to_uppercase();
+            self.cancel_attempt(&address);
+            self.states.entry(address).or_default().paused = true;
+        }
+    }
+
+    pub fn resume(&mut self, address: &str) {
+        if let Some(state) = self.states.get_mut(&address.to_uppercase()) {
+            state.paused = false;
+            state.backoff = INITIAL_BACKOFF;
+        }
+    }
+
+    pub fn is_paused(&self, address: &str) -> bool {
+        self.states
+            .get(&address.to_uppercase())
+            .is_some_and(|state| state.paused)
+    }
+
+    pub fn history(&self, address: &str) -> &[Attempt] {
+        self.states
+            .get(&address.to_uppercase())
+            .map_or(&[], |state| state.history.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use tokio::sync::oneshot;
+
+    static ADDRESS: &str = "AA:BB:CC:DD:EE:FF";
+
+    #[test]
+    fn test_non_favourite_is_ignored() {
+        let mut autoconnect = AutoConnect::new(vec![], None);
+
+        assert!(autoconnect.on_discovered(ADDRESS, false).is_none());
+        assert!(autoconnect.on_disconnected(ADDRESS).is_none());
+    }
+
+    #[test]
+    fn test_connect_on_discovery() {
+        let mut autoconnect = AutoConnect::new(vec![ADDRESS.to_lowercase()], None);
+
+        assert!(autoconnect.on_discovered(ADDRESS, true).is_none());
+        assert_eq!(
+            autoconnect.on_discovered(ADDRESS, false),
+            Some(Duration::ZERO)
+        );
+        // Already pending, until the connection ends
+        assert!(autoconnect.on_discovered(ADDRESS, false).is_none());
+        autoconnect.on_due(ADDRESS);
+        assert!(autoconnect.is_attempting(ADDRESS));
+        assert!(autoconnect.on_attempt(ADDRESS, None).is_none());
+        assert!(!autoconnect.is_attempting(ADDRESS));
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let mut autoconnect = AutoConnect::new(vec![ADDRESS.to_string()], None);
+
+        assert_eq!(autoconnect.on_disconnected(ADDRESS), Some(INITIAL_BACKOFF));
+        assert_eq!(
+            autoconnect.on_attempt(ADDRESS, Some("failed".to_string())),
+            Some(INITIAL_BACKOFF * 2)
+        );
+        assert_eq!(
+            autoconnect.on_attempt(ADDRESS, Some("failed".to_string())),
+            Some(INITIAL_BACKOFF * 4)
+        );
+        assert_eq!(
+            autoconnect.on_attempt(ADDRESS, Some("failed".to_string())),
+            Some(INITIAL_BACKOFF * 8)
+        );
+        assert!(autoconnect.on_attempt(ADDRESS, None).is_none());
+        assert_eq!(autoconnect.history(ADDRESS).len(), 4);
+        assert_eq!(autoconnect.on_disconnected(ADDRESS), Some(INITIAL_BACKOFF));
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let mut autoconnect = AutoConnect::new(vec![ADDRESS.to_string()], None);
+
+        autoconnect.on_disconnected(ADDRESS);
+        for _ in 0..20 {
+            autoconnect.on_attempt(ADDRESS, Some("failed".to_string()));
+        }
+
+        assert_eq!(
+            autoconnect.on_attempt(ADDRESS, Some("failed".to_string())),
+            Some(MAX_BACKOFF)
+        );
+        assert_eq!(autoconnect.history(ADDRESS).len(), HISTORY_LENGTH);
+    }
+
+    #[test]
+    fn test_manual_disconnect_pauses() {
+        let mut autoconnect = AutoConnect::new(vec![ADDRESS.to_string()], None);
+
+        autoconnect.on_discovered(ADDRESS, false);
+        autoconnect.on_attempt(ADDRESS, None);
+        autoconnect.pause(ADDRESS);
+
+        assert!(autoconnect.on_disconnected(ADDRESS).is_none());
+        assert!(autoconnect.on_discovered(ADDRESS, false).is_none());
+
+        autoconnect.resume(ADDRESS);
+        assert_eq!(autoconnect.on_disconnected(ADDRESS), Some(INITIAL_BACKOFF));
+    }
+
+    #[test]
+    fn test_toggle_favourite() {
+        let mut autoconnect = AutoConnect::new(vec![], None);
+
+        assert!(autoconnect.toggle_favourite(ADDRESS).unwrap());
+        assert!(autoconnect.is_favourite(&ADDRESS.to_lowercase()));
+        assert!(!autoconnect.toggle_favourite(ADDRESS).unwrap());
+        assert!(!autoconnect.is_favourite(ADDRESS));
+    }
+
+    #[test]
+    fn test_favourites_are_saved() {
+        let mut folder = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        folder.push("temp");
+        folder.push(format!(
+            "favourites-{}",
+            rand::thread_rng().gen_range(0..100000)
+        ));
+        std::fs::create_dir_all(&folder).unwrap();
+        let config_file = folder.join("config.toml");
+        std::fs::write(
+            &config_file,
+            "# Kept\nfavourites = [\"aa:bb:cc:dd:ee:ff\"]\n\n[layout]\nlist_size = 30\n",
+        )
+        .unwrap();
+
+        let mut autoconnect =
+            AutoConnect::new(vec![ADDRESS.to_lowercase()], Some(config_file.clone()));
+        assert!(autoconnect.toggle_favourite("11:22:33:44:55:66").unwrap());
+        assert!(!autoconnect.toggle_favourite(ADDRESS).unwrap());
+
+        let content = std::fs::read_to_string(&config_file).unwrap();
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        assert!(content.starts_with("# Kept\nfavourites = [\"11:22:33:44:55:66\"]\n"));
+        assert!(content.contains("[layout]\nlist_size = 30\n"));
+    }
+
+    /// Task that only ends when aborted, dropping `tx`
+    fn pending_task(tx: oneshot::Sender<()>) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let _tx = tx;
+            std::future::pending::<()>().await
+        })
+    }
+
+    async fn is_aborted(rx: oneshot::Receiver<()>) -> bool {
+        matches!(
+            tokio::time::timeout(Duration::from_secs(1), rx).await,
+            Ok(Err(_))
+        )
+    }
+
+    #[tokio::test]
+    async fn test_pause_aborts_the_scheduled_attempt() {
+        let (tx, rx) = oneshot::channel();
+        let mut autoconnect = AutoConnect::new(vec![ADDRESS.to_string()], None);
+        assert_eq!(autoconnect.on_disconnected(ADDRESS), Some(INITIAL_BACKOFF));
+        let task = pending_task(tx);
+        autoconnect.track_attempt(ADDRESS, task);
+
+        autoconnect.pause(ADDRESS);
+        assert!(is_aborted(rx).await);
+        autoconnect.resume(ADDRESS);
+        assert_eq!(autoconnect.on_disconnected(ADDRESS), Some(INITIAL_BACKOFF));
+    }
+
+    #[tokio::test]
+    async fn test_unfavourite_aborts_the_scheduled_attempt() {
+        let (tx, rx) = oneshot::channel();
+        let mut autoconnect = AutoConnect::new(vec![ADDRESS.to_string()], None);
+        autoconnect.on_discovered(ADDRESS, false);
+        let task = pending_task(tx);
+        autoconnect.track_attempt(ADDRESS, task);
+
+        autoconnect.toggle_favourite(ADDRESS).unwrap();
+        assert!(is_aborted(rx).await);
+    }
+}
@@ -0,0 +1,46 @@
+use btleplug::platform::PeripheralId;
+use zbus::{dbus_proxy, CacheProperties, Connection};
+
+#[dbus_proxy(interface = "org.bluez.Device1", default_service = "org.bluez")]
+trait Device1 {
+    #[dbus_proxy(property)]
+    fn paired(&self) -> zbus::Result<bool>;
+
+    #[dbus_proxy(property)]
+    fn trusted(&self) -> zbus::Result<bool>;
+
+    #[dbus_proxy(property)]
+    fn alias(&self) -> zbus::Result<String>;
+
+    #[dbus_proxy(property)]
+    fn set_alias(&self, alias: &str) -> zbus::Result<()>;
+
+    fn pair(&self) -> zbus::Result<()>;
+
+    fn cancel_pairing(&self) -> zbus::Result<()>;
+}
+
+/// Name of the D-Bus error behind an error of btleplug, which only
+/// keeps its message
+pub fn dbus_error_name(err: &(dyn std::error::Error + 'static)) -> Option<String> {
+    match err.downcast_ref::<bluez_async::BluetoothError>()? {
+        bluez_async::BluetoothError::DbusError(err) => err.name().map(str::to_string),
+        _ => None,
+    }
+}
+
+/// btleplug only displays the part of the object path after `/org/bluez/`
+pub fn device_path(periph_id: &PeripheralId) -> String {
+    format!("/org/bluez/{}", periph_id)
+}
+
+pub async fn device_proxy(
+    connection: &Connection,
+    periph_id: &PeripheralId,
+) -> zbus::Result<Device1Proxy<'static>> {
+    Device1Proxy::builder(connection)
+        .path(device_path(periph_id))?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await
+}
@@ -0,0 +1,321 @@
+use std::path::{Path, PathBuf};
+
+use btleplug::platform::PeripheralId;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{bluetooth::devices::Device, Error};
+
+/// Devices that are neither paired nor trusted are forgotten after this
+/// many seconds, as many BLE devices rotate random addresses
+static MAX_AGE: i64 = 30 * 24 * 3600;
+/// Devices that are neither paired nor trusted are listed on startup
+/// when seen within this many seconds
+static RECENT_AGE: i64 = 24 * 3600;
+static MAX_DEVICES: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedDevice {
+    pub address: String,
+    pub name: String,
+    pub alias: Option<String>,
+    /// Unix timestamps
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub paired: bool,
+    pub trusted: bool,
+    pub rssi: Option<i16>,
+    pub tx_power: Option<i16>,
+    pub periph_id: PeripheralId,
+}
+
+impl CachedDevice {
+    /// Devices of the user, kept and listed whenever they were seen
+    fn is_known(&self) -> bool {
+        self.paired || self.trusted
+    }
+}
+
+pub fn format_timestamp(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|datetime| {
+            datetime
+                .format(format_description!(
+                    "[year]-[month]-[day] [hour]:[minute]:[second]"
+                ))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+impl CachedDevice {
+    fn from_device(device: &Device, now: i64) -> CachedDevice {
+        CachedDevice {
+            address: device.address.clone(),
+            name: device.name.clone(),
+            alias: device.alias.clone(),
+            first_seen: now,
+            last_seen: now,
+            paired: device.paired,
+            trusted: device.trusted,
+            rssi: device.rssi,
+            tx_power: device.tx_power,
+            periph_id: device.periph_id.clone(),
+        }
+    }
+
+    pub fn to_device(&self) -> Device {
+        Device {
+            periph_id: self.periph_id.clone(),
+            address: self.address.clone(),
+            name: self.name.clone(),
+            alias: self.alias.clone(),
+            connected: false,
+            paired: self.paired,
+            trusted: self.trusted,
+            rssi: self.rssi,
+            tx_power: self.tx_power,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CacheFile {
+    #[serde(default)]
+    devices: Vec<CachedDevice>,
+}
+
+/// Every device seen in previous sessions, persisted in `devices.toml`
+#[derive(Debug, Default)]
+pub struct DeviceCache {
+    path: Option<PathBuf>,
+    devices: Vec<CachedDevice>,
+}
+
+impl DeviceCache {
+    pub fn load(path: PathBuf) -> DeviceCache {
+        let devices = match DeviceCache::read_from(&path) {
+            Ok(devices) => devices,
+            Err(Error::IOError(_)) => Vec::new(),
+            Err(err) => {
+                error!("{}, ignoring it", err);
+                Vec::new()
+            }
+        };
+        debug!("Loaded {} devices from the cache", devices.len());
+
+        let mut cache = DeviceCache {
+            path: Some(path),
+            devices,
+        };
+        cache.prune_at(OffsetDateTime::now_utc().unix_timestamp());
+        cache
+    }
+
+    /// Forget the devices that were not seen for too long, then the
+    /// oldest ones past `MAX_DEVICES`, known devices last
+    fn prune_at(&mut self, now: i64) {
+        self.devices
+            .retain(|cached| cached.is_known() || now - cached.last_seen <= MAX_AGE);
+
+        if self.devices.len() > MAX_DEVICES {
+            self.devices
+                .sort_by_key(|cached| std::cmp::Reverse((cached.is_known(), cached.last_seen)));
+            self.devices.truncate(MAX_DEVICES);
+            self.devices.sort_by_key(|cached| cached.first_seen);
+        }
+    }
+
+    fn read_from(path: &Path) -> Result<Vec<CachedDevice>, Error> {
+        let content = std::fs::read_to_string(path)?;
+
+        match toml::from_str::<CacheFile>(&content) {
+            Ok(file) => Ok(file.devices),
+            Err(_) => Err(Error::InvalidConfigFile(path.to_path_buf())),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            if let Some(folder) = path.parent() {
+                std::fs::create_dir_all(folder)?;
+            }
+            let content = toml::to_string(&CacheFile {
+                devices: self.devices.clone(),
+            })
+            .map_err(|err| Error::InvalidInput(err.to_string()))?;
+            std::fs::write(path, content)?;
+            debug!("Saved {} devices to the cache", self.devices.len());
+        }
+        Ok(())
+    }
+
+    pub fn update(&mut self, device: &Device) {
+        self.update_at(device, OffsetDateTime::now_utc().unix_timestamp());
+    }
+
+    fn update_at(&mut self, device: &Device, now: i64) {
+        if let Some(cached) = self
+            .devices
+            .iter_mut()
+            .find(|cached| cached.address.eq_ignore_ascii_case(&device.address))
+        {
+            cached.name = device.name.clone();
+            cached.alias = device.alias.clone();
+            cached.last_seen = now;
+            cached.paired = device.paired;
+            cached.trusted = device.trusted;
+            cached.rssi = device.rssi.or(cached.rssi);
+            cached.tx_power = device.tx_power.or(cached.tx_power);
+            cached.periph_id = device.periph_id.clone();
+        } else {
+            self.devices.push(CachedDevice::from_device(device, now));
+            self.prune_at(now);
+        }
+    }
+
+    pub fn get(&self, address: &str) -> Option<&CachedDevice> {
+        self.devices
+            .iter()
+            .find(|cached| cached.address.eq_ignore_ascii_case(address))
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = &CachedDevice> {
+        self.devices.iter()
+    }
+
+    /// Devices listed on startup : known ones, and the ones seen recently
+    pub fn startup_devices(&self) -> impl Iterator<Item = &CachedDevice> {
+        self.startup_devices_at(OffsetDateTime::now_utc().unix_timestamp())
+    }
+
+    fn startup_devices_at(&self, now: i64) -> impl Iterator<Item = &CachedDevice> {
+        self.devices
+            .iter()
+            .filter(move |cached| cached.is_known() || now - cached.last_seen <= RECENT_AGE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[derive(Deserialize)]
+    struct Id {
+        periph_id: PeripheralId,
+    }
+
+    fn unpaired(address: &str) -> Device {
+        Device {
+            address: address.to_string(),
+            paired: false,
+            ..device("Beacon", None)
+        }
+    }
+
+    fn device(name: &str, rssi: Option<i16>) -> Device {
+        let id: Id = toml::from_str(
+            "periph_id = { object_path = \"/org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF\" }",
+        )
+        .unwrap();
+        Device {
+            periph_id: id.periph_id,
+            address: "AA:BB:CC:DD:EE:FF".to_string(),
+            name: name.to_string(),
+            alias: None,
+            connected: true,
+            paired: true,
+            trusted: false,
+            rssi,
+            tx_power: None,
+        }
+    }
+
+    #[test]
+    fn test_update_keeps_first_seen_and_last_rssi() {
+        let mut cache = DeviceCache::default();
+
+        cache.update_at(&device("Headset", Some(-40)), 100);
+        cache.update_at(&device("Headset 2", None), 200);
+
+        let cached = cache.get("aa:bb:cc:dd:ee:ff").unwrap();
+        assert_eq!(cached.first_seen, 100);
+        assert_eq!(cached.last_seen, 200);
+        assert_eq!(cached.name, "Headset 2");
+        assert_eq!(cached.rssi, Some(-40));
+        assert_eq!(cache.devices().count(), 1);
+    }
+
+    #[test]
+    fn test_update_ignores_address_case() {
+        let mut cache = DeviceCache::default();
+        let mut lowercase = device("Headset", None);
+        lowercase.address = lowercase.address.to_lowercase();
+
+        cache.update_at(&device("Headset", None), 100);
+        cache.update_at(&lowercase, 200);
+
+        assert_eq!(cache.devices().count(), 1);
+        assert_eq!(cache.get("AA:BB:CC:DD:EE:FF").unwrap().last_seen, 200);
+    }
+
+    #[test]
+    fn test_old_and_extra_devices_are_forgotten() {
+        let mut cache = DeviceCache::default();
+        cache.update_at(&device("Headset", None), 0);
+        cache.update_at(&unpaired("11:22:33:44:55:66"), 0);
+        cache.update_at(&unpaired("66:55:44:33:22:11"), MAX_AGE);
+
+        cache.prune_at(MAX_AGE + RECENT_AGE);
+        let addresses: Vec<&str> = cache
+            .devices()
+            .map(|cached| cached.address.as_str())
+            .collect();
+        assert_eq!(addresses, ["AA:BB:CC:DD:EE:FF", "66:55:44:33:22:11"]);
+
+        let startup: Vec<&str> = cache
+            .startup_devices_at(MAX_AGE + RECENT_AGE + 1)
+            .map(|cached| cached.address.as_str())
+            .collect();
+        assert_eq!(startup, ["AA:BB:CC:DD:EE:FF"]);
+
+        for index in 0..MAX_DEVICES as i64 {
+            cache.update_at(
+                &unpaired(&format!(
+                    "00:00:00:00:{:02X}:{:02X}",
+                    index / 256,
+                    index % 256
+                )),
+                MAX_AGE + 1 + index,
+            );
+        }
+        assert_eq!(cache.devices().count(), MAX_DEVICES);
+        // The paired device is kept over the oldest unpaired one
+        assert!(cache.get("AA:BB:CC:DD:EE:FF").is_some());
+        assert!(cache.get("66:55:44:33:22:11").is_none());
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("temp");
+        path.push(format!("cache-{}", rand::thread_rng().gen_range(0..100000)));
+        path.push("devices.toml");
+
+        let mut cache = DeviceCache::load(path.clone());
+        cache.update_at(&device("Headset", Some(-40)), 100);
+        cache.save().unwrap();
+
+        let cache = DeviceCache::load(path.clone());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        let restored = cache.get("AA:BB:CC:DD:EE:FF").unwrap().to_device();
+        assert_eq!(restored, device("Headset", Some(-40)));
+        assert_eq!(restored.periph_id, device("", None).periph_id);
+        assert!(!restored.connected);
+        assert!(restored.paired);
+    }
+}
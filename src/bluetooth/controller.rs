@@ -1,17 +1,27 @@
 use crate::Error;
 use btleplug::{
-    api::{Central, CentralEvent, Manager as _, Peripheral as _, ScanFilter},
-    platform::{Adapter, Manager, PeripheralId},
+    api::{Central, CentralEvent, Manager as _, Peripheral as _, PeripheralProperties, ScanFilter},
+    platform::{Adapter, Manager, Peripheral, PeripheralId},
 };
 use futures::Stream;
-use log::{error, info};
+use log::{debug, info};
 use std::pin::Pin;
+use zbus::Connection;
 
-use crate::bluetooth::devices::Device;
+use crate::bluetooth::{bluez::device_proxy, devices::Device};
+
+#[derive(Default)]
+struct BluezInfo {
+    paired: bool,
+    trusted: bool,
+    alias: Option<String>,
+}
 
 #[derive(Clone)]
 pub struct BluetoothController {
     adapter: Adapter,
+    /// System bus connection used for what btleplug doesn't expose
+    bluez: Option<Connection>,
     pub scanning: bool,
 }
 
@@ -24,118 +34,202 @@ fn get_periph_name(props: Option<String>) -> String {
 }
 
 impl BluetoothController {
-    pub async fn from_first_adapter() -> BluetoothController {
-        let manager = Manager::new().await.unwrap();
-        let adapters = manager.adapters().await.unwrap();
-        let adapter = adapters.into_iter().next().unwrap();
+    pub async fn from_first_adapter() -> Result<BluetoothController, Error> {
+        let manager = Manager::new().await?;
+        let adapters = manager.adapters().await?;
+        let adapter = adapters.into_iter().next().ok_or(Error::NoAdapter)?;
 
-        BluetoothController {
+        Ok(BluetoothController {
             adapter,
+            bluez: None,
             scanning: false,
-        }
+        })
     }
 
     pub async fn from_adapter(id: &str) -> Result<BluetoothController, Error> {
         let manager = Manager::new().await?;
         let adapters = manager.adapters().await?;
         for adapter in adapters.into_iter() {
-            let adapter_id = adapter
-                .adapter_info()
-                .await
-                .unwrap()
-                .as_str()
-                .split(' ')
-                .next()
-                .unwrap()
-                .to_string();
-            if adapter_id == id {
+            let info = adapter.adapter_info().await?;
+            // The info looks like `hci0 (usb:v1D6Bp0246d0540)`
+            if info.split(' ').next() == Some(id) {
                 return Ok(BluetoothController {
                     adapter,
+                    bluez: None,
                     scanning: false,
                 });
             }
         }
-        Err(Error::InvalidInput(
-            "no adapter was found for the given name".to_string(),
-        ))
+        Err(Error::AdapterNotFound(id.to_string()))
+    }
+
+    pub fn set_bluez_connection(&mut self, connection: Connection) {
+        self.bluez = Some(connection);
     }
 
     /// Trigger the scan. Starting it will also power on the adapter
     /// if it is off
-    pub async fn trigger_scan(&mut self) -> Result<(), btleplug::Error> {
-        self.scanning = !self.scanning;
-        if !self.scanning {
+    pub async fn trigger_scan(&mut self) -> Result<(), Error> {
+        if self.scanning {
             info!("Stopping the scan");
             self.adapter.stop_scan().await?;
-            Ok(())
         } else {
             info!("Starting the scan");
             self.adapter.start_scan(ScanFilter::default()).await?;
-            Ok(())
         }
+        self.scanning = !self.scanning;
+        Ok(())
+    }
+
+    /// The peripheral and its properties, or `Error::PeripheralGone` if
+    /// BlueZ doesn't know it anymore
+    async fn peripheral(
+        &self,
+        periph_id: &PeripheralId,
+    ) -> Result<(Peripheral, PeripheralProperties), Error> {
+        let gone = || Error::PeripheralGone(periph_id.to_string());
+        let periph = self
+            .adapter
+            .peripheral(periph_id)
+            .await
+            .map_err(|err| match err {
+                btleplug::Error::DeviceNotFound => gone(),
+                err => err.into(),
+            })?;
+        let properties = periph.properties().await?.ok_or_else(gone)?;
+
+        Ok((periph, properties))
     }
 
     pub async fn connect(&self, periph_id: &PeripheralId) -> Result<(), Error> {
-        let periph = self.adapter.peripheral(periph_id).await?;
-        let properties = periph.properties().await.unwrap().unwrap();
+        let (periph, properties) = self.peripheral(periph_id).await?;
         let name = get_periph_name(properties.local_name);
 
         if periph.is_connected().await? {
             info!("Already connected to {}", name);
-            Ok(())
         } else {
             info!("Connecting to {}", name);
-            // periph.connect().await.expect();
-            match periph.connect().await {
-                Ok(()) => {}
-                Err(_) => {
-                    error!("Failed connecting to {}", name);
-                }
-            }
-            Ok(())
+            periph.connect().await?;
         }
+        Ok(())
     }
 
-    pub async fn disconnect(&self, periph_id: &PeripheralId) -> Result<(), btleplug::Error> {
-        let periph = self.adapter.peripheral(periph_id).await?;
-        let properties = periph.properties().await.unwrap().unwrap();
+    pub async fn disconnect(&self, periph_id: &PeripheralId) -> Result<(), Error> {
+        let (periph, properties) = self.peripheral(periph_id).await?;
         let name = get_periph_name(properties.local_name);
 
         if !periph.is_connected().await? {
             info!("Not connected to {}", name);
-            Ok(())
         } else {
             info!("Disconnecting from {}", name);
-            match periph.disconnect().await {
-                Ok(()) => {}
-                Err(_) => {
-                    error!("Failed disconnecting from {}", name);
-                }
-            };
-            Ok(())
+            periph.disconnect().await?;
         }
+        Ok(())
     }
 
     pub async fn events(
         &self,
-    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + std::marker::Send>>, btleplug::Error>
-    {
-        self.adapter.events().await
+    ) -> Result<Pin<Box<dyn Stream<Item = CentralEvent> + std::marker::Send>>, Error> {
+        Ok(self.adapter.events().await?)
     }
 
-    pub async fn get_device(&self, periph_id: &PeripheralId) -> Device {
-        let periph = self.adapter.peripheral(periph_id).await.unwrap();
-        let properties = periph.properties().await.unwrap().unwrap();
+    /// Paired and trusted flags and alias, read from BlueZ directly
+    async fn get_bluez_info(&self, periph_id: &PeripheralId) -> BluezInfo {
+        let Some(connection) = &self.bluez else {
+            return BluezInfo::default();
+        };
+
+        match device_proxy(connection, periph_id).await {
+            Ok(proxy) => BluezInfo {
+                paired: proxy.paired().await.unwrap_or(false),
+                trusted: proxy.trusted().await.unwrap_or(false),
+                alias: proxy.alias().await.ok(),
+            },
+            Err(err) => {
+                debug!("Could not read BlueZ info of {} : {}", periph_id, err);
+                BluezInfo::default()
+            }
+        }
+    }
 
-        Device {
+    pub fn has_bluez(&self) -> bool {
+        self.bluez.is_some()
+    }
+
+    /// Set the BlueZ alias of a device, an empty alias resets it.
+    /// Returns false if there is no BlueZ connection to do so
+    pub async fn set_alias(&self, periph_id: &PeripheralId, alias: &str) -> Result<bool, Error> {
+        let Some(connection) = &self.bluez else {
+            return Ok(false);
+        };
+
+        device_proxy(connection, periph_id)
+            .await?
+            .set_alias(alias)
+            .await?;
+        Ok(true)
+    }
+
+    /// Pair with a device through BlueZ, the agent answers its requests
+    pub async fn pair(&self, periph_id: &PeripheralId) -> Result<(), Error> {
+        let Some(connection) = &self.bluez else {
+            return Err(Error::InvalidInput(
+                "pairing needs a BlueZ connection".to_string(),
+            ));
+        };
+
+        info!("Pairing with {}", periph_id);
+        device_proxy(connection, periph_id).await?.pair().await?;
+        Ok(())
+    }
+
+    /// Stop a pairing in progress, including one started by a connection
+    pub async fn cancel_pairing(&self, periph_id: &PeripheralId) -> Result<(), Error> {
+        let Some(connection) = &self.bluez else {
+            return Ok(());
+        };
+
+        device_proxy(connection, periph_id)
+            .await?
+            .cancel_pairing()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_device(&self, periph_id: &PeripheralId) -> Result<Device, Error> {
+        let (periph, properties) = self.peripheral(periph_id).await?;
+        let info = self.get_bluez_info(periph_id).await;
+        let address = periph.address().to_string();
+        let name = get_periph_name(properties.local_name);
+
+        // BlueZ falls back to the name, or the address, when no alias was set
+        let alias = info
+            .alias
+            .filter(|alias| *alias != name && *alias != address.replace(':', "-"));
+
+        Ok(Device {
             periph_id: periph.id(),
-            address: periph.address().to_string(),
-            name: get_periph_name(properties.local_name),
-            connected: periph.is_connected().await.unwrap(),
-            // paired: periph.is_paired().await.unwrap(),
-            // trusted: periph.is_trusted().await.unwrap(),
+            address,
+            name,
+            alias,
+            connected: periph.is_connected().await?,
+            paired: info.paired,
+            trusted: info.trusted,
             rssi: properties.rssi,
             tx_power: properties.tx_power_level,
+        })
+    }
+
+    /// Paired devices BlueZ already knows about, available without scanning
+    pub async fn get_paired_devices(&self) -> Result<Vec<Device>, Error> {
+        let mut devices = Vec::new();
+        for periph in self.adapter.peripherals().await? {
+            match self.get_device(&periph.id()).await {
+                Ok(device) if device.paired => devices.push(device),
+                Ok(_) => {}
+                Err(err) => debug!("Skipping {} : {}", periph.id(), err),
+            }
         }
+        Ok(devices)
     }
 }
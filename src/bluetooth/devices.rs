@@ -8,20 +8,35 @@ use std::{
 };
 
 use btleplug::platform::PeripheralId;
-use tui::widgets::{ListItem, ListState};
+use serde::{Deserialize, Serialize};
+use tui::widgets::ListState;
 
-#[derive(Debug, Clone)]
+use crate::bluetooth::{
+    autoconnect::Favourites,
+    pins::{Pin, Pins},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     pub periph_id: PeripheralId,
     pub address: String,
+    /// Name advertised by the device
     pub name: String,
+    /// Name given by the user, through BlueZ or locally
+    pub alias: Option<String>,
     pub connected: bool,
-    // pub paired: bool,
-    // pub trusted: bool,
+    pub paired: bool,
+    pub trusted: bool,
     pub rssi: Option<i16>,
     pub tx_power: Option<i16>,
 }
 
+impl Device {
+    pub fn display_name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
 impl PartialEq for Device {
     fn eq(&self, other: &Device) -> bool {
         self.address == other.address
@@ -35,20 +50,144 @@ impl Hash for Device {
     }
 }
 
+/// A line of the devices list. Pinned devices come first, followed
+/// by a separator and every other device
+pub enum Row<'a> {
+    Pinned(&'a Pin, Option<&'a Device>),
+    Separator,
+    Device(&'a Device),
+}
+
+impl Row<'_> {
+    pub fn device(&self) -> Option<&Device> {
+        match self {
+            Row::Pinned(_, device) => *device,
+            Row::Separator => None,
+            Row::Device(device) => Some(device),
+        }
+    }
+
+    pub fn address(&self) -> Option<&str> {
+        match self {
+            Row::Pinned(pin, _) => Some(&pin.address),
+            Row::Separator => None,
+            Row::Device(device) => Some(&device.address),
+        }
+    }
+}
+
+/// Order of the devices that are not pinned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortOrder {
+    /// Order in which the devices were found
+    #[default]
+    Discovery,
+    Name,
+    /// Strongest signal first
+    Rssi,
+    Address,
+}
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 4] = [
+        SortOrder::Discovery,
+        SortOrder::Name,
+        SortOrder::Rssi,
+        SortOrder::Address,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortOrder::Discovery => "discovery",
+            SortOrder::Name => "name",
+            SortOrder::Rssi => "rssi",
+            SortOrder::Address => "address",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SortOrder> {
+        SortOrder::ALL
+            .into_iter()
+            .find(|order| order.name().eq_ignore_ascii_case(name))
+    }
+
+    fn sort(&self, devices: &mut [&Device]) {
+        match self {
+            SortOrder::Discovery => {}
+            SortOrder::Name => {
+                devices.sort_by_key(|device| device.display_name().to_lowercase());
+            }
+            SortOrder::Rssi => {
+                devices.sort_by_key(|device| std::cmp::Reverse(device.rssi.unwrap_or(i16::MIN)));
+            }
+            SortOrder::Address => devices.sort_by(|a, b| a.address.cmp(&b.address)),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Devices {
+    /// Index of the selected row as last drawn, see `selected`
     pub list_state: ListState,
+    /// Address of the selected row. Rows move when devices are sorted,
+    /// so the selection follows the device rather than the index
+    selected: Option<String>,
     hash_index_map: HashMap<u64, usize>,
     pub devices: Vec<Device>,
+    pub pins: Pins,
+    pub sort: SortOrder,
+    /// List the devices with an unknown name. They are kept either way
+    /// so that changing it shows or hides the devices already found
+    pub show_unknown: bool,
+    /// Favourites are listed whatever their name
+    pub favourites: Favourites,
+    offset: usize,
 }
 
 impl Devices {
     pub fn new() -> Devices {
         Devices {
             list_state: ListState::default(),
+            selected: None,
             hash_index_map: HashMap::<u64, usize>::new(),
             devices: Vec::new(),
+            pins: Pins::default(),
+            sort: SortOrder::default(),
+            show_unknown: false,
+            favourites: Favourites::default(),
+            offset: 0,
+        }
+    }
+
+    /// Devices with an unknown name are hidden unless asked for, or
+    /// explicitly followed by the user
+    pub fn is_listed(&self, device: &Device) -> bool {
+        device.display_name() != "Unknown"
+            || self.show_unknown
+            || self.favourites.contains(&device.address)
+            || self.pins.is_pinned(&device.address)
+    }
+
+    pub fn rows(&self) -> Vec<Row<'_>> {
+        let mut rows: Vec<Row> = self
+            .pins
+            .iter()
+            .map(|pin| Row::Pinned(pin, self.get_by_address(&pin.address)))
+            .collect();
+
+        let mut unpinned: Vec<&Device> = self
+            .devices
+            .iter()
+            .filter(|device| !self.pins.is_pinned(&device.address) && self.is_listed(device))
+            .collect();
+        self.sort.sort(&mut unpinned);
+
+        if !rows.is_empty() && !unpinned.is_empty() {
+            rows.push(Row::Separator);
         }
+        rows.extend(unpinned.into_iter().map(Row::Device));
+
+        rows
     }
 
     pub fn insert_or_replace(&mut self, device: Device) {
@@ -65,40 +204,166 @@ impl Devices {
         }
     }
 
+    /// Forget every device, when switching adapters. Pins are kept
+    pub fn clear(&mut self) {
+        self.devices.clear();
+        self.hash_index_map.clear();
+        self.select_index(None);
+    }
+
+    /// Index of the selected row in the current order
+    pub fn selected_index(&self) -> Option<usize> {
+        let selected = self.selected.as_deref()?;
+        self.rows().iter().position(|row| {
+            row.address()
+                .is_some_and(|address| address.eq_ignore_ascii_case(selected))
+        })
+    }
+
+    /// Position of the selected row among the rows of devices, which
+    /// leave out the separator
+    pub fn selected_position(&self) -> Option<usize> {
+        let index = self.selected_index()?;
+        let rows = self.rows();
+        Some(
+            rows[..index]
+                .iter()
+                .filter(|row| row.address().is_some())
+                .count(),
+        )
+    }
+
+    fn select_index(&mut self, index: Option<usize>) {
+        self.selected =
+            index.and_then(|index| self.rows().get(index)?.address().map(str::to_string));
+        self.list_state.select(index);
+    }
+
     pub fn move_selector_down(&mut self) {
-        let current_index = self.list_state.selected();
+        let rows = self.rows();
+        let current_index = self.selected_index();
+
+        let index = if let Some(index) = current_index {
+            min(index + 1, rows.len().saturating_sub(1))
+        } else {
+            0
+        };
 
-        if let Some(index) = current_index {
-            self.list_state
-                .select(Some(min(index + 1, self.devices.len() - 1)));
-        } else if !self.devices.is_empty() {
-            self.list_state.select(Some(0));
+        if rows.is_empty() {
+            return;
         }
+        let index = if let Row::Separator = rows[index] {
+            index + 1
+        } else {
+            index
+        };
+        self.select_index(Some(index));
     }
 
     pub fn move_selector_up(&mut self) {
-        let current_index = self.list_state.selected();
+        let rows = self.rows();
+        let current_index = self.selected_index();
 
-        if let Some(index) = current_index {
-            self.list_state.select(Some(index.saturating_sub(1)));
-        } else if !self.devices.is_empty() {
-            self.list_state.select(Some(0));
+        if rows.is_empty() {
+            return;
         }
+        let index = if let Some(index) = current_index {
+            index.saturating_sub(1)
+        } else {
+            0
+        };
+        let index = if let Row::Separator = rows[index] {
+            index - 1
+        } else {
+            index
+        };
+        self.select_index(Some(index));
     }
 
-    pub fn list_items<'a>(&self) -> Vec<ListItem<'a>> {
-        self.devices
-            .clone()
-            .into_iter()
-            // .filter(|device| !hide_unnamed || device.name != "Unknown")
-            .map(ListItem::new)
-            .collect()
+    /// Select a row from its index, unless it is the separator
+    pub fn select_row(&mut self, index: usize) -> bool {
+        match self.rows().get(index) {
+            Some(Row::Separator) | None => false,
+            Some(_) => {
+                self.select_index(Some(index));
+                true
+            }
+        }
+    }
+
+    /// Index of the first visible row. The list widget keeps its own
+    /// offset private, this mirrors how it scrolls to the selection
+    pub fn update_offset(&mut self, height: usize) -> usize {
+        let len = self.rows_len();
+        // The selected device may have moved since the last frame
+        self.list_state.select(self.selected_index());
+        let Some(selected) = self.list_state.selected() else {
+            self.offset = 0;
+            return 0;
+        };
+        if len == 0 || height == 0 {
+            return self.offset;
+        }
+
+        let selected = min(selected, len - 1);
+        let mut start = min(self.offset, len - 1);
+        let end = start + min(height, len - start);
+        if selected >= end {
+            start = selected + 1 - height;
+        }
+        if selected < start {
+            start = selected;
+        }
+
+        self.offset = start;
+        start
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn get_selected_row(&self) -> Option<Row<'_>> {
+        let index = self.selected_index()?;
+        self.rows().into_iter().nth(index)
     }
 
     pub async fn get_selected_device(&self) -> Option<Device> {
-        self.list_state
-            .selected()
-            .map(|index| self.devices[index].clone())
+        self.get_selected_row()
+            .and_then(|row| row.device().cloned())
+    }
+
+    /// Pin or unpin the selected row, returning the new pinned state
+    pub fn toggle_selected_pin(&mut self) -> Option<bool> {
+        let (address, name) = match self.get_selected_row()? {
+            Row::Pinned(pin, _) => (pin.address.clone(), pin.name.clone()),
+            Row::Device(device) => (device.address.clone(), device.display_name().to_string()),
+            Row::Separator => return None,
+        };
+        let pinned = self.pins.toggle(&address, &name);
+
+        // The selector stays on the same device now that it moved
+        self.list_state.select(self.selected_index());
+
+        Some(pinned)
+    }
+
+    pub fn rows_len(&self) -> usize {
+        self.rows().len()
+    }
+
+    /// Number of rows of devices, pinned or not
+    pub fn device_rows_len(&self) -> usize {
+        self.rows()
+            .iter()
+            .filter(|row| row.address().is_some())
+            .count()
+    }
+
+    pub fn get_by_address(&self, address: &str) -> Option<&Device> {
+        self.devices
+            .iter()
+            .find(|device| device.address.eq_ignore_ascii_case(address))
     }
 
     pub fn len(&self) -> usize {
@@ -115,3 +380,98 @@ impl Default for Devices {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth::autoconnect::AutoConnect;
+
+    #[derive(Deserialize)]
+    struct Id {
+        periph_id: PeripheralId,
+    }
+
+    fn device(address: &str, name: &str, rssi: i16) -> Device {
+        let id: Id = toml::from_str(&format!(
+            "periph_id = {{ object_path = \"/org/bluez/hci0/dev_{}\" }}",
+            address.replace(':', "_")
+        ))
+        .unwrap();
+        Device {
+            periph_id: id.periph_id,
+            address: address.to_string(),
+            name: name.to_string(),
+            alias: None,
+            connected: false,
+            paired: false,
+            trusted: false,
+            rssi: Some(rssi),
+            tx_power: None,
+        }
+    }
+
+    fn selected_name(devices: &Devices) -> Option<String> {
+        devices
+            .get_selected_row()
+            .and_then(|row| row.device().map(|device| device.name.clone()))
+    }
+
+    #[test]
+    fn test_selection_follows_the_device() {
+        let mut devices = Devices::new();
+        devices.sort = SortOrder::Rssi;
+        devices.insert_or_replace(device("AA:BB:CC:DD:EE:FF", "Headphones", -40));
+        devices.insert_or_replace(device("11:22:33:44:55:66", "Keyboard", -60));
+        devices.move_selector_down();
+        devices.move_selector_down();
+        assert_eq!(selected_name(&devices).as_deref(), Some("Keyboard"));
+
+        devices.insert_or_replace(device("11:22:33:44:55:66", "Keyboard", -30));
+
+        assert_eq!(selected_name(&devices).as_deref(), Some("Keyboard"));
+        assert_eq!(devices.selected_index(), Some(0));
+        devices.update_offset(10);
+        assert_eq!(devices.list_state.selected(), Some(0));
+        devices.move_selector_down();
+        assert_eq!(selected_name(&devices).as_deref(), Some("Headphones"));
+    }
+
+    #[test]
+    fn test_positions_skip_the_separator() {
+        let mut devices = Devices::new();
+        devices.insert_or_replace(device("AA:BB:CC:DD:EE:FF", "Headphones", -40));
+        devices.insert_or_replace(device("11:22:33:44:55:66", "Keyboard", -60));
+        devices.insert_or_replace(device("66:55:44:33:22:11", "Mouse", -70));
+        devices.move_selector_down();
+        devices.toggle_selected_pin();
+        devices.move_selector_down();
+        devices.move_selector_down();
+
+        assert_eq!(devices.rows_len(), 4);
+        assert_eq!(devices.device_rows_len(), 3);
+        assert_eq!(selected_name(&devices).as_deref(), Some("Mouse"));
+        assert_eq!(devices.selected_index(), Some(3));
+        assert_eq!(devices.selected_position(), Some(2));
+    }
+
+    #[test]
+    fn test_unknown_devices_are_hidden() {
+        let mut devices = Devices::new();
+        devices.insert_or_replace(device("AA:BB:CC:DD:EE:FF", "Headphones", -40));
+        devices.insert_or_replace(device("11:22:33:44:55:66", "Unknown", -60));
+        devices.insert_or_replace(device("66:55:44:33:22:11", "Unknown", -70));
+        assert_eq!(devices.rows_len(), 1);
+
+        // Favourites toggled afterwards are listed right away
+        let mut autoconnect = AutoConnect::new(vec![], None);
+        devices.favourites = autoconnect.favourites();
+        autoconnect.toggle_favourite("11:22:33:44:55:66").unwrap();
+        assert_eq!(devices.rows_len(), 2);
+
+        // Devices found before the setting changed are shown too
+        devices.show_unknown = true;
+        assert_eq!(devices.rows_len(), 3);
+        devices.show_unknown = false;
+        assert_eq!(devices.rows_len(), 2);
+    }
+}
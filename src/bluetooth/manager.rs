@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use log::{debug, warn};
+use tokio::{
+    sync::{mpsc::Sender, oneshot},
+    time::timeout,
+};
+use zbus::{dbus_interface, Connection, DBusError, SignalContext};
+
+use crate::{
+    bluetooth::devices::Device,
+    events::{manager::ManagerEvent, AppEvent},
+};
+
+/// Longer than the timeouts of the operations requests wait for
+static TIMEOUT: Duration = Duration::from_secs(40);
+
+#[derive(Debug, Clone, DBusError, PartialEq, Eq)]
+#[dbus_error(prefix = "bltui.Error", impl_display = true)]
+pub enum ManagerError {
+    UnknownDevice(String),
+    Failed(String),
+    Timeout(String),
+}
+
+struct ManagerServer {
+    tx: Sender<AppEvent>,
+}
+
+impl ManagerServer {
+    async fn forward<T>(
+        &self,
+        event: ManagerEvent,
+        rx: oneshot::Receiver<Result<T, ManagerError>>,
+    ) -> Result<T, ManagerError> {
+        self.tx
+            .send(AppEvent::Manager(event))
+            .await
+            .map_err(|_| ManagerError::Failed("bltui is shutting down".to_string()))?;
+
+        match timeout(TIMEOUT, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(ManagerError::Failed("request was dropped".to_string())),
+            Err(_) => Err(ManagerError::Timeout("no answer in time".to_string())),
+        }
+    }
+}
+
+#[dbus_interface(name = "bltui.Manager1")]
+impl ManagerServer {
+    async fn connect(&self, address: String) -> Result<(), ManagerError> {
+        let (tx, rx) = oneshot::channel();
+        self.forward(ManagerEvent::Connect { address, tx }, rx)
+            .await
+    }
+
+    async fn disconnect(&self, address: String) -> Result<(), ManagerError> {
+        let (tx, rx) = oneshot::channel();
+        self.forward(ManagerEvent::Disconnect { address, tx }, rx)
+            .await
+    }
+
+    async fn scan(&self, enable: bool) -> Result<(), ManagerError> {
+        let (tx, rx) = oneshot::channel();
+        self.forward(ManagerEvent::Scan { enable, tx }, rx).await
+    }
+
+    #[dbus_interface(signal)]
+    async fn device_found(ctxt: &SignalContext<'_>, address: &str, name: &str) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn device_connected(
+        ctxt: &SignalContext<'_>,
+        address: &str,
+        name: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// Control interface exposed by a running bltui on the bus it
+/// shares with the agent
+pub struct Manager<'a> {
+    path: zvariant::ObjectPath<'a>,
+    connection: Connection,
+}
+
+impl Manager<'static> {
+    pub fn new(connection: Connection, path: String) -> Manager<'static> {
+        Manager {
+            path: zvariant::ObjectPath::try_from(path).unwrap(),
+            connection,
+        }
+    }
+
+    pub async fn start_server(&self, tx: Sender<AppEvent>) {
+        self.connection
+            .object_server()
+            .at(&self.path, ManagerServer { tx })
+            .await
+            .unwrap();
+
+        debug!("Started the manager server")
+    }
+
+    fn signal_context(&self) -> SignalContext<'_> {
+        SignalContext::from_parts(self.connection.clone(), self.path.clone())
+    }
+
+    pub async fn device_found(&self, device: &Device) {
+        if let Err(err) = ManagerServer::device_found(
+            &self.signal_context(),
+            &device.address,
+            device.display_name(),
+        )
+        .await
+        {
+            warn!("Failed emitting DeviceFound : {}", err);
+        }
+    }
+
+    pub async fn device_connected(&self, device: &Device) {
+        if let Err(err) = ManagerServer::device_connected(
+            &self.signal_context(),
+            &device.address,
+            device.display_name(),
+        )
+        .await
+        {
+            warn!("Failed emitting DeviceConnected : {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bluetooth::private_bus::PrivateBus;
+
+    async fn start_manager(
+        bus: &PrivateBus,
+    ) -> (Connection, tokio::sync::mpsc::Receiver<AppEvent>) {
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let connection = bus.connection().await;
+        let manager = Manager::new(connection.clone(), "/bltui/manager".into());
+        manager.start_server(tx).await;
+        (connection, rx)
+    }
+
+    async fn call(
+        bus: &PrivateBus,
+        server: &Connection,
+        method: &'static str,
+        body: impl serde::Serialize + zvariant::DynamicType + Send + Sync + 'static,
+    ) -> tokio::task::JoinHandle<zbus::Result<std::sync::Arc<zbus::Message>>> {
+        let client = bus.connection().await;
+        let destination = server.unique_name().unwrap().to_owned();
+        tokio::spawn(async move {
+            client
+                .call_method(
+                    Some(destination),
+                    "/bltui/manager",
+                    Some("bltui.Manager1"),
+                    method,
+                    &body,
+                )
+                .await
+        })
+    }
+
+    #[tokio::test]
+    async fn test_connect_is_forwarded() {
+        let bus = PrivateBus::new();
+        let (server, mut rx) = start_manager(&bus).await;
+
+        let reply = call(&bus, &server, "Connect", ("AA:BB:CC:DD:EE:FF",)).await;
+
+        match rx.recv().await.unwrap() {
+            AppEvent::Manager(ManagerEvent::Connect { address, tx }) => {
+                assert_eq!(address, "AA:BB:CC:DD:EE:FF");
+                tx.send(Ok(())).unwrap();
+            }
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+
+        assert!(reply.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_scan_is_forwarded() {
+        let bus = PrivateBus::new();
+        let (server, mut rx) = start_manager(&bus).await;
+
+        let reply = call(&bus, &server, "Scan", (true,)).await;
+
+        match rx.recv().await.unwrap() {
+            AppEvent::Manager(ManagerEvent::Scan { enable, tx }) => {
+                assert!(enable);
+                tx.send(Ok(())).unwrap();
+            }
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+
+        assert!(reply.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_device_error() {
+        let bus = PrivateBus::new();
+        let (server, mut rx) = start_manager(&bus).await;
+
+        let reply = call(&bus, &server, "Disconnect", ("AA:BB:CC:DD:EE:FF",)).await;
+
+        match rx.recv().await.unwrap() {
+            AppEvent::Manager(ManagerEvent::Disconnect { address, tx }) => {
+                tx.send(Err(ManagerError::UnknownDevice(address))).unwrap();
+            }
+            ev => panic!("Unexpected event {:?}", ev),
+        }
+
+        match reply.await.unwrap() {
+            Err(zbus::Error::MethodError(name, _, _)) => {
+                assert_eq!(name.as_str(), "bltui.Error.UnknownDevice");
+            }
+            res => panic!("Unexpected reply {:?}", res),
+        }
+    }
+}
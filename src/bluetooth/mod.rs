@@ -1,3 +1,12 @@
 pub mod agent;
+pub mod autoconnect;
+pub mod bluez;
+pub mod cache;
 pub mod controller;
 pub mod devices;
+pub mod manager;
+pub mod operations;
+pub mod pins;
+
+#[cfg(test)]
+mod private_bus;
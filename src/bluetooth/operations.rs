@@ -0,0 +1,414 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::{bluetooth::manager::ManagerError, Error};
+
+static SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+/// Time each frame of the spinner is shown
+pub static SPINNER_RATE: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Operation {
+    Connect,
+    Disconnect,
+    Pair,
+}
+
+impl Operation {
+    pub fn progress(&self) -> &'static str {
+        match self {
+            Operation::Connect => "connecting...",
+            Operation::Disconnect => "disconnecting...",
+            Operation::Pair => "pairing...",
+        }
+    }
+
+    /// Start of the message once the operation succeeded, followed by
+    /// the device name
+    pub fn done(&self) -> &'static str {
+        match self {
+            Operation::Connect => "Connected to",
+            Operation::Disconnect => "Disconnected from",
+            Operation::Pair => "Paired with",
+        }
+    }
+
+    pub fn failed(&self) -> &'static str {
+        match self {
+            Operation::Connect => "Failed connecting to",
+            Operation::Disconnect => "Failed disconnecting from",
+            Operation::Pair => "Failed pairing with",
+        }
+    }
+
+    pub fn cancelled(&self) -> &'static str {
+        match self {
+            Operation::Connect => "Cancelled connecting to",
+            Operation::Disconnect => "Cancelled disconnecting from",
+            Operation::Pair => "Cancelled pairing with",
+        }
+    }
+
+    /// Time after which the operation is abandoned. Pairing waits for
+    /// the user to confirm the passkey
+    pub fn timeout(&self) -> Duration {
+        match self {
+            Operation::Connect => Duration::from_secs(30),
+            Operation::Disconnect => Duration::from_secs(10),
+            Operation::Pair => Duration::from_secs(60),
+        }
+    }
+
+    /// Whether BlueZ may be pairing during the operation, which then
+    /// needs `CancelPairing` to be stopped
+    pub fn may_pair(&self) -> bool {
+        matches!(self, Operation::Connect | Operation::Pair)
+    }
+}
+
+/// Told how a run ended, for the requests made over D-Bus
+pub type Waiter = oneshot::Sender<Result<(), ManagerError>>;
+
+#[derive(Debug)]
+struct Running {
+    /// Tells this run from a later one of the same operation
+    id: u64,
+    name: String,
+    started: Instant,
+    task: JoinHandle<()>,
+    waiters: Vec<Waiter>,
+}
+
+impl Running {
+    /// Abort the task if it is still going, and tell the waiters
+    fn end(self, result: Result<(), ManagerError>) {
+        self.task.abort();
+        for waiter in self.waiters {
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+/// An operation in progress, as shown in the operations pane
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunningOperation {
+    pub address: String,
+    pub name: String,
+    pub operation: Operation,
+    pub elapsed: Duration,
+}
+
+/// Tasks started on devices and not finished yet, by address and
+/// operation
+#[derive(Debug, Default)]
+pub struct Operations {
+    running: HashMap<(String, Operation), Running>,
+    next_id: u64,
+}
+
+impl Operations {
+    pub fn is_running(&self, address: &str, operation: Operation) -> bool {
+        self.running
+            .contains_key(&(address.to_uppercase(), operation))
+    }
+
+    /// Track the task of an operation, which must not be running
+    /// already. `spawn` gets the id the task reports its result with
+    pub fn start(
+        &mut self,
+        address: &str,
+        name: String,
+        operation: Operation,
+        spawn: impl FnOnce(u64) -> JoinHandle<()>,
+    ) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.running.insert(
+            (address.to_uppercase(), operation),
+            Running {
+                id,
+                name,
+                started: Instant::now(),
+                task: spawn(id),
+                waiters: Vec::new(),
+            },
+        );
+    }
+
+    /// Tell `waiter` how the running `operation` on `address` ends
+    pub fn wait(&mut self, address: &str, operation: Operation, waiter: Waiter) {
+        match self.running.get_mut(&(address.to_uppercase(), operation)) {
+            Some(running) => running.waiters.push(waiter),
+            None => {
+                let _ = waiter.send(Err(ManagerError::Failed(format!(
+                    "not {}",
+                    operation.progress()
+                ))));
+            }
+        }
+    }
+
+    /// Stop tracking `operation` on `address` once the device changed
+    /// on its own, aborting its task. Returns whether it was running
+    pub fn finish(&mut self, address: &str, operation: Operation) -> bool {
+        match self.running.remove(&(address.to_uppercase(), operation)) {
+            Some(running) => {
+                running.end(Ok(()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop tracking the run `id` of `operation` once its task returned
+    /// `result`. Returns false if that run was already finished or
+    /// cancelled, even when a later run is going on
+    pub fn finish_run(
+        &mut self,
+        address: &str,
+        operation: Operation,
+        id: u64,
+        result: &Result<(), Error>,
+    ) -> bool {
+        let key = (address.to_uppercase(), operation);
+        if self.running.get(&key).map(|running| running.id) != Some(id) {
+            return false;
+        }
+
+        if let Some(running) = self.running.remove(&key) {
+            running.end(match result {
+                Ok(()) => Ok(()),
+                Err(err @ Error::Timeout(_)) => Err(ManagerError::Timeout(err.to_string())),
+                Err(err) => Err(ManagerError::Failed(err.to_string())),
+            });
+        }
+        true
+    }
+
+    /// Abort every operation running on `address`, returning them
+    pub fn cancel(&mut self, address: &str) -> Vec<Operation> {
+        let address = address.to_uppercase();
+        let operations: Vec<Operation> = self
+            .running
+            .keys()
+            .filter(|(running_address, _)| *running_address == address)
+            .map(|(_, operation)| *operation)
+            .collect();
+
+        for operation in &operations {
+            if let Some(running) = self.running.remove(&(address.clone(), *operation)) {
+                running.end(Err(ManagerError::Failed("cancelled".to_string())));
+            }
+        }
+
+        operations
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.running.len()
+    }
+
+    /// Running operations, oldest first
+    pub fn list(&self) -> Vec<RunningOperation> {
+        let now = Instant::now();
+        let mut operations: Vec<(Instant, RunningOperation)> = self
+            .running
+            .iter()
+            .map(|((address, operation), running)| {
+                (
+                    running.started,
+                    RunningOperation {
+                        address: address.clone(),
+                        name: running.name.clone(),
+                        operation: *operation,
+                        elapsed: now.duration_since(running.started),
+                    },
+                )
+            })
+            .collect();
+        operations.sort_by_key(|(started, _)| *started);
+
+        operations
+            .into_iter()
+            .map(|(_, operation)| operation)
+            .collect()
+    }
+
+    /// Spinner followed by what is being done, for the devices list
+    pub fn status(&self, address: &str) -> Option<String> {
+        self.status_at(Instant::now(), address)
+    }
+
+    fn status_at(&self, now: Instant, address: &str) -> Option<String> {
+        let address = address.to_uppercase();
+        // The latest operation is shown when there are several
+        let (operation, running) = self
+            .running
+            .iter()
+            .filter(|((running_address, _), _)| *running_address == address)
+            .map(|((_, operation), running)| (operation, running))
+            .max_by_key(|(_, running)| running.started)?;
+
+        Some(format!(
+            "{} {}",
+            spinner_frame(now.duration_since(running.started)),
+            operation.progress()
+        ))
+    }
+}
+
+pub fn spinner_frame(elapsed: Duration) -> &'static str {
+    let frame = elapsed.as_millis() / SPINNER_RATE.as_millis();
+    SPINNER[frame as usize % SPINNER.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idle_task() -> JoinHandle<()> {
+        tokio::spawn(std::future::pending())
+    }
+
+    #[tokio::test]
+    async fn test_finish_matching_operation() {
+        let mut operations = Operations::default();
+        operations.start(
+            "aa:bb:cc:dd:ee:ff",
+            "Headphones".to_string(),
+            Operation::Connect,
+            |_| idle_task(),
+        );
+
+        assert!(operations.is_running("AA:BB:CC:DD:EE:FF", Operation::Connect));
+        assert!(!operations.finish("AA:BB:CC:DD:EE:FF", Operation::Disconnect));
+        assert!(operations.finish("AA:BB:CC:DD:EE:FF", Operation::Connect));
+        assert!(operations.is_empty());
+        assert!(!operations.finish("AA:BB:CC:DD:EE:FF", Operation::Connect));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_aborts_the_tasks() {
+        let mut operations = Operations::default();
+        operations.start(
+            "AA:BB:CC:DD:EE:FF",
+            "Headphones".to_string(),
+            Operation::Pair,
+            |_| idle_task(),
+        );
+        operations.start(
+            "11:22:33:44:55:66",
+            "Keyboard".to_string(),
+            Operation::Connect,
+            |_| idle_task(),
+        );
+
+        assert_eq!(
+            operations.cancel("aa:bb:cc:dd:ee:ff"),
+            vec![Operation::Pair]
+        );
+        assert_eq!(operations.len(), 1);
+        assert_eq!(operations.list()[0].name, "Keyboard");
+        assert!(operations.cancel("AA:BB:CC:DD:EE:FF").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_spinner() {
+        let mut operations = Operations::default();
+        operations.start(
+            "AA:BB:CC:DD:EE:FF",
+            "Headphones".to_string(),
+            Operation::Disconnect,
+            |_| idle_task(),
+        );
+        let started =
+            operations.running[&("AA:BB:CC:DD:EE:FF".to_string(), Operation::Disconnect)].started;
+
+        assert_eq!(
+            operations.status_at(started, "AA:BB:CC:DD:EE:FF"),
+            Some("⠋ disconnecting...".to_string())
+        );
+        assert_eq!(
+            operations.status_at(started + SPINNER_RATE * 12, "AA:BB:CC:DD:EE:FF"),
+            Some("⠹ disconnecting...".to_string())
+        );
+        assert_eq!(operations.status_at(started, "11:22:33:44:55:66"), None);
+    }
+
+    #[tokio::test]
+    async fn test_finish_aborts_and_ignores_the_previous_run() {
+        let mut operations = Operations::default();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let mut first = None;
+        operations.start(
+            "AA:BB:CC:DD:EE:FF",
+            "Headphones".to_string(),
+            Operation::Connect,
+            |id| {
+                first = Some(id);
+                tokio::spawn(async move {
+                    let _tx = tx;
+                    std::future::pending::<()>().await
+                })
+            },
+        );
+
+        // Connected before the task returned
+        assert!(operations.finish("AA:BB:CC:DD:EE:FF", Operation::Connect));
+        let aborted = tokio::time::timeout(Duration::from_secs(1), rx).await;
+        assert!(matches!(aborted, Ok(Err(_))));
+
+        operations.start(
+            "AA:BB:CC:DD:EE:FF",
+            "Headphones".to_string(),
+            Operation::Connect,
+            |_| idle_task(),
+        );
+        let first = first.unwrap();
+        assert!(!operations.finish_run("AA:BB:CC:DD:EE:FF", Operation::Connect, first, &Ok(())));
+        assert!(operations.is_running("AA:BB:CC:DD:EE:FF", Operation::Connect));
+        assert!(operations.finish_run("AA:BB:CC:DD:EE:FF", Operation::Connect, first + 1, &Ok(())));
+        assert!(operations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_waiters_get_the_result() {
+        let mut operations = Operations::default();
+        let mut id = 0;
+        operations.start(
+            "AA:BB:CC:DD:EE:FF",
+            "Headphones".to_string(),
+            Operation::Connect,
+            |run| {
+                id = run;
+                idle_task()
+            },
+        );
+        let (first, first_rx) = oneshot::channel();
+        let (second, second_rx) = oneshot::channel();
+        operations.wait("aa:bb:cc:dd:ee:ff", Operation::Connect, first);
+        operations.wait("AA:BB:CC:DD:EE:FF", Operation::Connect, second);
+
+        operations.finish_run(
+            "AA:BB:CC:DD:EE:FF",
+            Operation::Connect,
+            id,
+            &Err(Error::Timeout(Operation::Connect.timeout())),
+        );
+        assert!(matches!(first_rx.await, Ok(Err(ManagerError::Timeout(_)))));
+        assert!(matches!(second_rx.await, Ok(Err(ManagerError::Timeout(_)))));
+
+        let (idle, idle_rx) = oneshot::channel();
+        operations.wait("AA:BB:CC:DD:EE:FF", Operation::Disconnect, idle);
+        assert!(matches!(idle_rx.await, Ok(Err(ManagerError::Failed(_)))));
+    }
+}
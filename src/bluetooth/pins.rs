@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pin {
+    pub address: String,
+    pub name: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PinsFile {
+    #[serde(default)]
+    pins: Vec<Pin>,
+}
+
+/// Devices pinned at the top of the list, persisted in `pins.toml`
+#[derive(Debug, Clone, Default)]
+pub struct Pins {
+    path: Option<PathBuf>,
+    pins: Vec<Pin>,
+}
+
+impl Pins {
+    pub fn load(path: PathBuf) -> Pins {
+        let pins = match Pins::read_from(&path) {
+            Ok(pins) => pins,
+            Err(Error::IOError(_)) => Vec::new(),
+            Err(err) => {
+                error!("{}, ignoring it", err);
+                Vec::new()
+            }
+        };
+
+        Pins {
+            path: Some(path),
+            pins,
+        }
+    }
+
+    fn read_from(path: &Path) -> Result<Vec<Pin>, Error> {
+        let content = std::fs::read_to_string(path)?;
+
+        match toml::from_str::<PinsFile>(&content) {
+            Ok(file) => Ok(file.pins),
+            Err(_) => Err(Error::InvalidConfigFile(path.to_path_buf())),
+        }
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            if let Some(folder) = path.parent() {
+                std::fs::create_dir_all(folder)?;
+            }
+            let content = toml::to_string(&PinsFile {
+                pins: self.pins.clone(),
+            })
+            .map_err(|err| Error::InvalidInput(err.to_string()))?;
+            std::fs::write(path, content)?;
+            debug!("Saved pins to {:?}", path);
+        }
+        Ok(())
+    }
+
+    pub fn is_pinned(&self, address: &str) -> bool {
+        self.pins
+            .iter()
+            .any(|pin| pin.address.eq_ignore_ascii_case(address))
+    }
+
+    /// Returns whether the device is now pinned
+    pub fn toggle(&mut self, address: &str, name: &str) -> bool {
+        let pinned = if self.is_pinned(address) {
+            self.pins
+                .retain(|pin| !pin.address.eq_ignore_ascii_case(address));
+            false
+        } else {
+            self.pins.push(Pin {
+                address: address.to_uppercase(),
+                name: name.to_string(),
+            });
+            true
+        };
+
+        if let Err(err) = self.save() {
+            error!("Failed saving pins : {}", err);
+        }
+
+        pinned
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Pin> {
+        self.pins.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn temp_pins_path() -> PathBuf {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("temp");
+        path.push(format!("pins-{}", rand::thread_rng().gen_range(0..100000)));
+        path.push("pins.toml");
+        path
+    }
+
+    #[test]
+    fn test_pins_round_trip() {
+        let path = temp_pins_path();
+
+        let mut pins = Pins::load(path.clone());
+        assert!(pins.is_empty());
+        assert!(pins.toggle("aa:bb:cc:dd:ee:ff", "Headset"));
+        assert!(pins.toggle("11:22:33:44:55:66", "Phone"));
+        assert!(!pins.toggle("11:22:33:44:55:66", "Phone"));
+
+        let pins = Pins::load(path.clone());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(
+            pins.iter().cloned().collect::<Vec<Pin>>(),
+            vec![Pin {
+                address: "AA:BB:CC:DD:EE:FF".to_string(),
+                name: "Headset".to_string(),
+            }]
+        );
+        assert!(pins.is_pinned("AA:BB:CC:DD:EE:FF"));
+    }
+
+    #[test]
+    fn test_pins_invalid_file() {
+        let path = temp_pins_path();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "pins = 3").unwrap();
+
+        let pins = Pins::load(path.clone());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert!(pins.is_empty());
+    }
+}
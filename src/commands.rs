@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bluetooth::devices::SortOrder,
+    keymap::{Action, Context},
+    Error,
+};
+
+/// Something the user asked for, through a key or the command palette
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Command {
+    Action(Action),
+    /// Scan for the given duration, then stop
+    ScanFor(Duration),
+    StopScan,
+    Adapter(String),
+    Sort(SortOrder),
+}
+
+/// A command of the palette, as shown to the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub args: &'static str,
+    pub description: &'static str,
+}
+
+/// Every command of the palette, the main view actions followed by
+/// the commands taking arguments
+pub fn specs() -> Vec<CommandSpec> {
+    let mut specs: Vec<CommandSpec> = Action::ALL
+        .into_iter()
+        .filter(|action| action.contexts().contains(&Context::Main) && *action != Action::Palette)
+        .map(|action| CommandSpec {
+            name: action.name(),
+            args: if action == Action::Scan {
+                "[seconds]"
+            } else {
+                ""
+            },
+            description: action.description(),
+        })
+        .collect();
+
+    specs.push(CommandSpec {
+        name: "adapter",
+        args: "<name>",
+        description: "switch to another adapter",
+    });
+    specs.push(CommandSpec {
+        name: "sort",
+        args: "<discovery|name|rssi|address>",
+        description: "sort the devices",
+    });
+
+    specs
+}
+
+/// Score of `query` as a subsequence of `candidate`, higher is better.
+/// Consecutive characters and a match at the start score more
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut candidate_chars = candidate.char_indices();
+
+    for q in query.chars().map(|c| c.to_ascii_lowercase()) {
+        let (index, _) = candidate_chars.find(|(_, c)| c.to_ascii_lowercase() == q)?;
+        score += match previous {
+            None if index == 0 => 10,
+            Some(previous) if previous + 1 == index => 5,
+            _ => 1,
+        };
+        previous = Some(index);
+    }
+
+    Some(score - candidate.len() as i32)
+}
+
+/// Commands whose name fuzzy matches the first word of `input`,
+/// best first
+pub fn complete(input: &str) -> Vec<CommandSpec> {
+    let query = input.split_whitespace().next().unwrap_or("");
+
+    let mut matches: Vec<(i32, CommandSpec)> = specs()
+        .into_iter()
+        .filter_map(|spec| fuzzy_score(query, spec.name).map(|score| (score, spec)))
+        .collect();
+    matches.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    matches.into_iter().map(|(_, spec)| spec).collect()
+}
+
+impl Command {
+    /// Parse a palette input like `scan 30`. The command name is fuzzy
+    /// matched, `con` is enough for `connect`
+    pub fn parse(input: &str) -> Result<Command, Error> {
+        let mut words = input.split_whitespace();
+        let query = words
+            .next()
+            .ok_or_else(|| Error::InvalidInput("empty command".to_string()))?;
+        let args: Vec<&str> = words.collect();
+
+        let spec = specs()
+            .into_iter()
+            .find(|spec| spec.name == query)
+            .or_else(|| complete(query).into_iter().next())
+            .ok_or_else(|| Error::InvalidInput(format!("unknown command `{}`", query)))?;
+        let usage = || Error::InvalidInput(format!("usage : {} {}", spec.name, spec.args));
+
+        match (spec.name, args.as_slice()) {
+            ("scan", [seconds]) => {
+                let seconds = seconds.parse().map_err(|_| usage())?;
+                Ok(Command::ScanFor(Duration::from_secs(seconds)))
+            }
+            ("adapter", [name]) => Ok(Command::Adapter(name.to_string())),
+            ("sort", [order]) => SortOrder::from_name(order)
+                .map(Command::Sort)
+                .ok_or_else(usage),
+            (name, []) => Action::from_name(name)
+                .map(Command::Action)
+                .ok_or_else(usage),
+            _ => Err(usage()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert!(fuzzy_score("cnt", "connect").is_some());
+        assert!(fuzzy_score("tnc", "connect").is_none());
+        assert!(fuzzy_score("con", "connect") > fuzzy_score("con", "disconnect"));
+    }
+
+    #[test]
+    fn test_complete() {
+        let names: Vec<&str> = complete("dis").iter().map(|spec| spec.name).collect();
+        assert_eq!(names.first(), Some(&"disconnect"));
+
+        assert_eq!(complete("").len(), specs().len());
+        assert!(!specs().iter().any(|spec| spec.name == "palette"));
+    }
+
+    #[test]
+    fn test_parse_actions() {
+        assert_eq!(
+            Command::parse("connect").unwrap(),
+            Command::Action(Action::Connect)
+        );
+        assert_eq!(
+            Command::parse("  conn ").unwrap(),
+            Command::Action(Action::Connect)
+        );
+        assert_eq!(
+            Command::parse("scan").unwrap(),
+            Command::Action(Action::Scan)
+        );
+        assert!(Command::parse("connect now").is_err());
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("xyz").is_err());
+    }
+
+    #[test]
+    fn test_parse_arguments() {
+        assert_eq!(
+            Command::parse("scan 30").unwrap(),
+            Command::ScanFor(Duration::from_secs(30))
+        );
+        assert_eq!(
+            Command::parse("adapter hci1").unwrap(),
+            Command::Adapter("hci1".to_string())
+        );
+        assert_eq!(
+            Command::parse("sort RSSI").unwrap(),
+            Command::Sort(SortOrder::Rssi)
+        );
+        assert!(Command::parse("scan soon").is_err());
+        assert!(Command::parse("sort colour").is_err());
+        assert!(Command::parse("adapter").is_err());
+    }
+}
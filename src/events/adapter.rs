@@ -1,16 +1,32 @@
 use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::bluetooth::devices::Device;
 
 use super::AppEvent;
 
+/// Adapter event along with the device it is about, as read from BlueZ
+/// when the event was received
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DeviceEvent {
+    Discovered(Device),
+    Connected(Device),
+    Disconnected(Device),
+    Updated(Device),
+}
+
+pub type AdapterEvents =
+    std::pin::Pin<Box<dyn futures::Stream<Item = btleplug::api::CentralEvent> + std::marker::Send>>;
+
 pub async fn spawn_adapter_watcher(
-    mut events: std::pin::Pin<
-        Box<dyn futures::Stream<Item = btleplug::api::CentralEvent> + std::marker::Send>,
-    >,
+    mut events: AdapterEvents,
     tx: tokio::sync::mpsc::Sender<AppEvent>,
-) {
+) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
         while let Some(event) = events.next().await {
-            tx.send(AppEvent::Adapter(event)).await.unwrap();
+            if tx.send(AppEvent::Adapter(event)).await.is_err() {
+                break;
+            }
         }
-    });
+    })
 }
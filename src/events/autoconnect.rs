@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use super::AppEvent;
+
+#[derive(Debug)]
+pub enum AutoConnectEvent {
+    /// Time to connect to a favourite, through a connect operation
+    Due { address: String },
+}
+
+/// Send `AutoConnectEvent::Due` for `address` after `delay`
+pub fn spawn_connect_attempt(
+    address: String,
+    delay: Duration,
+    tx: tokio::sync::mpsc::Sender<AppEvent>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        let _ = tx
+            .send(AppEvent::AutoConnect(AutoConnectEvent::Due { address }))
+            .await;
+    })
+}
@@ -0,0 +1,62 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use super::AppEvent;
+
+/// What tells that the file was written, `None` while it doesn't exist
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Send an `AppEvent::ConfigChanged` when the config file is written,
+/// created or removed, checking it every `period`
+pub fn spawn_config_watcher(
+    path: PathBuf,
+    period: Duration,
+    tx: tokio::sync::mpsc::Sender<AppEvent>,
+) {
+    let mut ticker = tokio::time::interval(period);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    tokio::spawn(async move {
+        let mut version = file_version(&path);
+        loop {
+            ticker.tick().await;
+            let current = file_version(&path);
+            if current == version {
+                continue;
+            }
+            version = current;
+            if tx.send(AppEvent::ConfigChanged).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_changes_are_sent() {
+        let path = std::env::temp_dir().join(format!("bltui-watch-{}.toml", std::process::id()));
+        std::fs::write(&path, "mouse = true").unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        spawn_config_watcher(path.clone(), Duration::from_millis(10), tx);
+
+        let unchanged = tokio::time::timeout(Duration::from_millis(50), rx.recv()).await;
+        assert!(unchanged.is_err());
+
+        std::fs::write(&path, "mouse = false").unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await;
+        assert!(matches!(event, Ok(Some(AppEvent::ConfigChanged))));
+
+        std::fs::remove_file(&path).unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await;
+        assert!(matches!(event, Ok(Some(AppEvent::ConfigChanged))));
+    }
+}
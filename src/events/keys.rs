@@ -1,13 +1,65 @@
-use crossterm::event::{poll, Event};
+use crossterm::event::{Event, EventStream};
+use futures::StreamExt;
+use log::error;
+use tokio::task::JoinHandle;
 
 use super::AppEvent;
 
-pub fn spawn_keypress_watcher(tx: tokio::sync::mpsc::Sender<AppEvent>) {
-    std::thread::spawn(move || loop {
-        if poll(std::time::Duration::from_millis(2)).unwrap() {
-            if let Event::Key(key) = crossterm::event::read().unwrap() {
-                tx.blocking_send(AppEvent::Input(key)).unwrap();
+/// Event of the app for a terminal event
+fn app_event(event: Event) -> AppEvent {
+    match event {
+        Event::Key(key) => AppEvent::Input(key),
+        Event::Mouse(mouse) => AppEvent::Mouse(mouse),
+        Event::Resize(width, height) => AppEvent::Resize(width, height),
+        Event::Paste(text) => AppEvent::Paste(text),
+        Event::FocusGained => AppEvent::Focus(true),
+        Event::FocusLost => AppEvent::Focus(false),
+    }
+}
+
+/// Forward the terminal events until the terminal stops sending them,
+/// which ends with an `AppEvent::InputClosed`
+pub fn spawn_input_watcher(tx: tokio::sync::mpsc::Sender<AppEvent>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut events = EventStream::new();
+        let error = loop {
+            match events.next().await {
+                Some(Ok(event)) => {
+                    if tx.send(app_event(event)).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Err(err)) => {
+                    error!("Failed reading terminal events : {}", err);
+                    break Some(err);
+                }
+                None => break None,
             }
-        }
-    });
+        };
+        let _ = tx.send(AppEvent::InputClosed(error)).await;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    #[test]
+    fn test_app_event() {
+        let key = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        assert!(matches!(app_event(Event::Key(key)), AppEvent::Input(k) if k == key));
+        assert!(matches!(
+            app_event(Event::Resize(80, 24)),
+            AppEvent::Resize(80, 24)
+        ));
+        assert!(matches!(
+            app_event(Event::Paste("hci1".to_string())),
+            AppEvent::Paste(text) if text == "hci1"
+        ));
+        assert!(matches!(
+            app_event(Event::FocusLost),
+            AppEvent::Focus(false)
+        ));
+    }
 }
@@ -0,0 +1,11 @@
+use crate::bluetooth::manager::ManagerError;
+use tokio::sync::oneshot::Sender;
+
+type Responder<T> = Sender<Result<T, ManagerError>>;
+
+#[derive(Debug)]
+pub enum ManagerEvent {
+    Connect { address: String, tx: Responder<()> },
+    Disconnect { address: String, tx: Responder<()> },
+    Scan { enable: bool, tx: Responder<()> },
+}
@@ -1,17 +1,49 @@
 use btleplug::api::CentralEvent;
-use crossterm::event::KeyEvent;
+use crossterm::event::{KeyEvent, MouseEvent};
+
+use crate::commands::Command;
 
 pub mod adapter;
 pub mod agent;
+pub mod autoconnect;
+pub mod config;
 pub mod keys;
+pub mod manager;
+pub mod operation;
+pub mod record;
+pub mod signals;
 pub mod tick;
 
+use adapter::DeviceEvent;
 use agent::AgentEvent;
+use autoconnect::AutoConnectEvent;
+use manager::ManagerEvent;
+use operation::OperationEvent;
 
 #[derive(Debug)]
 pub enum AppEvent {
     Input(KeyEvent),
+    Mouse(MouseEvent),
+    Resize(u16, u16),
+    /// Text pasted in the terminal
+    Paste(String),
+    /// Whether the terminal gained or lost the focus
+    Focus(bool),
+    /// No more terminal events will come, after an error or not
+    InputClosed(Option<std::io::Error>),
+    /// A signal asking the app to stop was received
+    Signal(&'static str),
+    /// A task panicked, the app stops once it cleaned up
+    Panicked,
     Tick,
+    /// The config file was written, created or removed
+    ConfigChanged,
     Adapter(CentralEvent),
+    /// Adapter event once the device was read
+    Device(DeviceEvent),
     Agent(AgentEvent),
+    Manager(ManagerEvent),
+    AutoConnect(AutoConnectEvent),
+    Command(Command),
+    Operation(OperationEvent),
 }
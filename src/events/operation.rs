@@ -0,0 +1,85 @@
+use std::future::Future;
+
+use tokio::task::JoinHandle;
+
+use crate::{bluetooth::operations::Operation, Error};
+
+use super::AppEvent;
+
+#[derive(Debug)]
+pub enum OperationEvent {
+    Finished {
+        /// Run of the operation, from `Operations::start`
+        id: u64,
+        address: String,
+        /// Name of the device shown to the user
+        name: String,
+        operation: Operation,
+        result: Result<(), Error>,
+    },
+}
+
+/// Run an operation on a device in the background, sending its result.
+/// It fails with `Error::Timeout` if it takes longer than the timeout of
+/// the operation
+pub fn spawn_operation<F>(
+    id: u64,
+    address: String,
+    name: String,
+    operation: Operation,
+    task: F,
+    tx: tokio::sync::mpsc::Sender<AppEvent>,
+) -> JoinHandle<()>
+where
+    F: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let result = match tokio::time::timeout(operation.timeout(), task).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::Timeout(operation.timeout())),
+        };
+        let _ = tx
+            .send(AppEvent::Operation(OperationEvent::Finished {
+                id,
+                address,
+                name,
+                operation,
+                result,
+            }))
+            .await;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_operation_times_out() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        spawn_operation(
+            0,
+            "AA:BB:CC:DD:EE:FF".to_string(),
+            "Headphones".to_string(),
+            Operation::Disconnect,
+            async {
+                tokio::time::sleep(Duration::from_secs(3600)).await;
+                Ok(())
+            },
+            tx,
+        );
+
+        match rx.recv().await {
+            Some(AppEvent::Operation(OperationEvent::Finished {
+                operation, result, ..
+            })) => {
+                assert_eq!(operation, Operation::Disconnect);
+                assert!(
+                    matches!(result, Err(Error::Timeout(timeout)) if timeout == Operation::Disconnect.timeout())
+                );
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+}
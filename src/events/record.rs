@@ -0,0 +1,335 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::{KeyEvent, MouseEvent};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::{bluetooth::operations::Operation, commands::Command, Error};
+
+use super::{
+    adapter::DeviceEvent, agent::AgentEvent, autoconnect::AutoConnectEvent,
+    operation::OperationEvent, AppEvent,
+};
+
+/// Request of the pairing agent, without the channel to answer it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AgentRequest {
+    Release,
+    RequestPincode,
+    DisplayPincode { pincode: String },
+    RequestPasskey,
+    DisplayPasskey { passkey: u32 },
+    RequestConfirmation { passkey: u32 },
+    RequestAuthorization,
+    AuthorizeService { uuid: String },
+    Cancel,
+}
+
+/// An event of a session, as written in a recording. Frames are not
+/// recorded, and neither are D-Bus requests of the manager
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    Resize(u16, u16),
+    Paste(String),
+    Focus(bool),
+    Device(DeviceEvent),
+    Agent(AgentRequest),
+    AutoConnect {
+        address: String,
+    },
+    Operation {
+        /// Operations are started in the same order when replaying, so
+        /// their runs get the same ids
+        id: u64,
+        address: String,
+        name: String,
+        operation: Operation,
+        error: Option<String>,
+    },
+    Command(Command),
+}
+
+/// A line of a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Time since the start of the session
+    pub elapsed_ms: u64,
+    pub event: RecordedEvent,
+}
+
+impl RecordedEvent {
+    pub fn from_app_event(event: &AppEvent) -> Option<RecordedEvent> {
+        let recorded = match event {
+            AppEvent::Input(key) => RecordedEvent::Key(*key),
+            AppEvent::Mouse(mouse) => RecordedEvent::Mouse(*mouse),
+            AppEvent::Resize(width, height) => RecordedEvent::Resize(*width, *height),
+            AppEvent::Paste(text) => RecordedEvent::Paste(text.clone()),
+            AppEvent::Focus(gained) => RecordedEvent::Focus(*gained),
+            AppEvent::Device(event) => RecordedEvent::Device(event.clone()),
+            AppEvent::Agent(event) => RecordedEvent::Agent(match event {
+                AgentEvent::Release { .. } => AgentRequest::Release,
+                AgentEvent::RequestPincode { .. } => AgentRequest::RequestPincode,
+                AgentEvent::DisplayPincode { pincode, .. } => AgentRequest::DisplayPincode {
+                    pincode: pincode.clone(),
+                },
+                AgentEvent::RequestPasskey { .. } => AgentRequest::RequestPasskey,
+                AgentEvent::DisplayPasskey { passkey, .. } => {
+                    AgentRequest::DisplayPasskey { passkey: *passkey }
+                }
+                AgentEvent::RequestConfirmation { passkey, .. } => {
+                    AgentRequest::RequestConfirmation { passkey: *passkey }
+                }
+                AgentEvent::RequestAuthorization { .. } => AgentRequest::RequestAuthorization,
+                AgentEvent::AuthorizeService { uuid, .. } => {
+                    AgentRequest::AuthorizeService { uuid: uuid.clone() }
+                }
+                AgentEvent::Cancel { .. } => AgentRequest::Cancel,
+            }),
+            AppEvent::AutoConnect(AutoConnectEvent::Due { address }) => {
+                RecordedEvent::AutoConnect {
+                    address: address.clone(),
+                }
+            }
+            AppEvent::Operation(OperationEvent::Finished {
+                id,
+                address,
+                name,
+                operation,
+                result,
+            }) => RecordedEvent::Operation {
+                id: *id,
+                address: address.clone(),
+                name: name.clone(),
+                operation: *operation,
+                error: result.as_ref().err().map(|err| err.to_string()),
+            },
+            AppEvent::Command(command) => RecordedEvent::Command(command.clone()),
+            AppEvent::InputClosed(_)
+            | AppEvent::Signal(_)
+            | AppEvent::Panicked
+            | AppEvent::Tick
+            | AppEvent::ConfigChanged
+            | AppEvent::Adapter(_)
+            | AppEvent::Manager(_) => return None,
+        };
+        Some(recorded)
+    }
+
+    /// The event to handle when replaying. Answers to the agent requests
+    /// go nowhere
+    pub fn into_app_event(self) -> AppEvent {
+        fn tx<T>() -> oneshot::Sender<T> {
+            oneshot::channel().0
+        }
+
+        match self {
+            RecordedEvent::Key(key) => AppEvent::Input(key),
+            RecordedEvent::Mouse(mouse) => AppEvent::Mouse(mouse),
+            RecordedEvent::Resize(width, height) => AppEvent::Resize(width, height),
+            RecordedEvent::Paste(text) => AppEvent::Paste(text),
+            RecordedEvent::Focus(gained) => AppEvent::Focus(gained),
+            RecordedEvent::Device(event) => AppEvent::Device(event),
+            RecordedEvent::Agent(request) => AppEvent::Agent(match request {
+                AgentRequest::Release => AgentEvent::Release { tx: tx() },
+                AgentRequest::RequestPincode => AgentEvent::RequestPincode { tx: tx() },
+                AgentRequest::DisplayPincode { pincode } => {
+                    AgentEvent::DisplayPincode { pincode, tx: tx() }
+                }
+                AgentRequest::RequestPasskey => AgentEvent::RequestPasskey { tx: tx() },
+                AgentRequest::DisplayPasskey { passkey } => {
+                    AgentEvent::DisplayPasskey { passkey, tx: tx() }
+                }
+                AgentRequest::RequestConfirmation { passkey } => {
+                    AgentEvent::RequestConfirmation { passkey, tx: tx() }
+                }
+                AgentRequest::RequestAuthorization => AgentEvent::RequestAuthorization { tx: tx() },
+                AgentRequest::AuthorizeService { uuid } => {
+                    AgentEvent::AuthorizeService { uuid, tx: tx() }
+                }
+                AgentRequest::Cancel => AgentEvent::Cancel { tx: tx() },
+            }),
+            RecordedEvent::AutoConnect { address } => {
+                AppEvent::AutoConnect(AutoConnectEvent::Due { address })
+            }
+            RecordedEvent::Operation {
+                id,
+                address,
+                name,
+                operation,
+                error,
+            } => AppEvent::Operation(OperationEvent::Finished {
+                id,
+                address,
+                name,
+                operation,
+                result: match error {
+                    Some(message) => Err(Error::Recorded(message)),
+                    None => Ok(()),
+                },
+            }),
+            RecordedEvent::Command(command) => AppEvent::Command(command),
+        }
+    }
+}
+
+/// Writes the events of the session to a file, one JSON entry per line
+pub struct Recorder {
+    file: File,
+    started: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Recorder, Error> {
+        Ok(Recorder {
+            file: File::create(path)?,
+            started: Instant::now(),
+        })
+    }
+
+    /// Write `event` if it is recorded. Lines are written right away so
+    /// that nothing is lost if the app crashes
+    pub fn record(&mut self, event: &AppEvent) -> Result<(), Error> {
+        let Some(event) = RecordedEvent::from_app_event(event) else {
+            return Ok(());
+        };
+        let entry = Entry {
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            event,
+        };
+        let line =
+            serde_json::to_string(&entry).map_err(|err| Error::InvalidInput(err.to_string()))?;
+        writeln!(self.file, "{}", line)?;
+        Ok(())
+    }
+}
+
+/// Read every entry of a recording, failing on the first invalid line
+pub fn read_recording(path: &Path) -> Result<Vec<Entry>, Error> {
+    parse_recording(BufReader::new(File::open(path)?))
+}
+
+fn parse_recording(reader: impl BufRead) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|err| Error::InvalidRecording {
+            line: index + 1,
+            message: err.to_string(),
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Send the recorded events at the time they happened, `speed` times
+/// faster
+pub fn spawn_replay(
+    entries: Vec<Entry>,
+    speed: f64,
+    tx: tokio::sync::mpsc::Sender<AppEvent>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let started = tokio::time::Instant::now();
+        for entry in entries {
+            let at = Duration::from_millis(entry.elapsed_ms).div_f64(speed);
+            tokio::time::sleep_until(started + at).await;
+            if tx.send(entry.event.into_app_event()).await.is_err() {
+                return;
+            }
+        }
+        log::info!("End of the replayed session");
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::Action;
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    #[test]
+    fn test_parse_recording() {
+        let recording = concat!(
+            r#"{"elapsed_ms":0,"event":{"Resize":[80,24]}}"#,
+            "\n\n",
+            r#"{"elapsed_ms":1500,"event":{"Command":{"Action":"Connect"}}}"#,
+            "\n",
+            r#"{"elapsed_ms":2000,"event":{"Operation":{"id":0,"address":"AA:BB:CC:DD:EE:FF","name":"Headphones","operation":"Connect","error":"Timed out after 30s"}}}"#,
+        );
+
+        let entries = parse_recording(recording.as_bytes()).unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].elapsed_ms, 1500);
+        assert!(matches!(
+            entries[1].event.clone().into_app_event(),
+            AppEvent::Command(Command::Action(Action::Connect))
+        ));
+        match entries[2].event.clone().into_app_event() {
+            AppEvent::Operation(OperationEvent::Finished { result, .. }) => {
+                assert_eq!(result.unwrap_err().to_string(), "Timed out after 30s")
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_invalid_line() {
+        let recording = "{\"elapsed_ms\":0,\"event\":{\"Focus\":true}}\n{\"elapsed_ms\":";
+
+        let err = parse_recording(recording.as_bytes()).unwrap_err();
+
+        assert!(matches!(err, Error::InvalidRecording { line: 2, .. }));
+    }
+
+    #[test]
+    fn test_agent_requests_keep_their_data() {
+        let (tx, _rx) = oneshot::channel();
+        let event = AppEvent::Agent(AgentEvent::RequestConfirmation {
+            passkey: 123456,
+            tx,
+        });
+
+        let recorded = RecordedEvent::from_app_event(&event).unwrap();
+
+        assert!(matches!(
+            recorded,
+            RecordedEvent::Agent(AgentRequest::RequestConfirmation { passkey: 123456 })
+        ));
+        assert!(RecordedEvent::from_app_event(&AppEvent::Tick).is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replay_speed() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let key = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE);
+        let entries = vec![
+            Entry {
+                elapsed_ms: 0,
+                event: RecordedEvent::Focus(true),
+            },
+            Entry {
+                elapsed_ms: 4000,
+                event: RecordedEvent::Key(key),
+            },
+        ];
+
+        let started = tokio::time::Instant::now();
+        spawn_replay(entries, 2.0, tx);
+
+        assert!(matches!(rx.recv().await, Some(AppEvent::Focus(true))));
+        assert!(matches!(rx.recv().await, Some(AppEvent::Input(k)) if k == key));
+        assert_eq!(started.elapsed(), Duration::from_secs(2));
+        assert!(rx.recv().await.is_none());
+    }
+}
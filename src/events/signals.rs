@@ -0,0 +1,51 @@
+use log::{error, warn};
+use tokio::signal::unix::{signal, SignalKind};
+
+use super::AppEvent;
+
+/// Send an `AppEvent::Signal` on SIGINT, SIGTERM or SIGHUP, so they stop
+/// the app like quitting does
+pub fn spawn_signal_watcher(tx: tokio::sync::mpsc::Sender<AppEvent>) {
+    for (kind, name) in [
+        (SignalKind::interrupt(), "SIGINT"),
+        (SignalKind::terminate(), "SIGTERM"),
+        (SignalKind::hangup(), "SIGHUP"),
+    ] {
+        let mut signals = match signal(kind) {
+            Ok(signals) => signals,
+            Err(err) => {
+                error!("Failed listening for {} : {}", name, err);
+                continue;
+            }
+        };
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            while signals.recv().await.is_some() {
+                warn!("Received {}", name);
+                if tx.send(AppEvent::Signal(name)).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_hangup_is_forwarded() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        spawn_signal_watcher(tx);
+
+        std::process::Command::new("kill")
+            .args(["-HUP", &std::process::id().to_string()])
+            .status()
+            .unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await;
+        assert!(matches!(event, Ok(Some(AppEvent::Signal("SIGHUP")))));
+    }
+}
@@ -1,12 +1,102 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::Notify;
+
 use super::AppEvent;
 
-pub fn spawn_ticker(tick_rate: std::time::Duration, tx: tokio::sync::mpsc::Sender<AppEvent>) {
-    let mut ticker = tokio::time::interval(tick_rate);
+/// Asks for a frame to be drawn. Requests made before the frame is
+/// drawn are merged into a single one
+#[derive(Debug, Clone)]
+pub struct FrameRequester {
+    notify: Arc<Notify>,
+}
+
+impl FrameRequester {
+    pub fn request(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Request a frame after `delay`, to animate a widget
+    pub fn request_in(&self, delay: Duration) {
+        let frames = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            frames.request();
+        });
+    }
+}
+
+/// Send an `AppEvent::Tick` whenever a frame was requested, at most
+/// `max_fps` times per second. Nothing is sent while idle
+pub fn spawn_frame_scheduler(
+    max_fps: u32,
+    tx: tokio::sync::mpsc::Sender<AppEvent>,
+) -> FrameRequester {
+    let notify = Arc::new(Notify::new());
+    let frame_interval = Duration::from_secs(1) / max_fps.max(1);
+
+    let requests = notify.clone();
+    tokio::spawn(async move {
+        loop {
+            requests.notified().await;
+            if tx.send(AppEvent::Tick).await.is_err() {
+                break;
+            }
+            tokio::time::sleep(frame_interval).await;
+        }
+    });
+
+    FrameRequester { notify }
+}
+
+/// Request a frame every `period`, for widgets that change on their
+/// own like the logger
+pub fn spawn_refresh_timer(period: Duration, frames: FrameRequester) {
+    let mut ticker = tokio::time::interval(period);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
     tokio::spawn(async move {
         loop {
             ticker.tick().await;
-            tx.send(AppEvent::Tick).await.unwrap();
+            frames.request();
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::advance;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_frame_while_idle() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let frames = spawn_frame_scheduler(60, tx);
+
+        advance(Duration::from_secs(1)).await;
+        assert!(rx.try_recv().is_err());
+
+        frames.request();
+        assert!(matches!(rx.recv().await, Some(AppEvent::Tick)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_are_merged_and_capped() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        let frames = spawn_frame_scheduler(10, tx);
+
+        // Half a second of requests, at 10 frames per second
+        for _ in 0..50 {
+            for _ in 0..100 {
+                frames.request();
+            }
+            advance(Duration::from_millis(10)).await;
+        }
+
+        let mut ticks = 0;
+        while let Ok(AppEvent::Tick) = rx.try_recv() {
+            ticks += 1;
+        }
+        assert_eq!(ticks, 5);
+    }
+}
@@ -0,0 +1,239 @@
+use std::{collections::HashSet, time::Duration};
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{process::Command, time::timeout};
+
+use crate::bluetooth::devices::Device;
+
+fn default_timeout() -> u64 {
+    10
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HookEvent {
+    Connected,
+    Disconnected,
+    Discovered,
+}
+
+impl HookEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HookEvent::Connected => "connected",
+            HookEvent::Disconnected => "disconnected",
+            HookEvent::Discovered => "discovered",
+        }
+    }
+}
+
+/// A user command run when a device event matches. An unset
+/// `address` or `name` pattern matches every device
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    pub event: HookEvent,
+    pub address: Option<String>,
+    pub name: Option<String>,
+    pub command: String,
+    /// Seconds after which the command is killed
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+}
+
+/// Case insensitive glob matching, supporting `*` and `?`
+fn pattern_matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let value: Vec<char> = value.to_lowercase().chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if let Some((star_p, star_v)) = backtrack {
+            p = star_p + 1;
+            v = star_v + 1;
+            backtrack = Some((star_p, star_v + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl Hook {
+    pub fn matches(&self, event: HookEvent, device: &Device) -> bool {
+        self.event == event
+            && self
+                .address
+                .as_deref()
+                .is_none_or(|pattern| pattern_matches(pattern, &device.address))
+            && self.name.as_deref().is_none_or(|pattern| {
+                pattern_matches(pattern, device.display_name())
+                    || pattern_matches(pattern, &device.name)
+            })
+    }
+
+    async fn run(self, event: HookEvent, device: Device) {
+        debug!("Running hook `{}`", self.command);
+
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("BLTUI_EVENT", event.as_str())
+            .env("BLTUI_ADDRESS", &device.address)
+            .env("BLTUI_NAME", device.display_name())
+            .env(
+                "BLTUI_RSSI",
+                device.rssi.map(|rssi| rssi.to_string()).unwrap_or_default(),
+            )
+            .kill_on_drop(true)
+            .spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                error!("Failed spawning hook `{}` : {}", self.command, err);
+                return;
+            }
+        };
+
+        match timeout(Duration::from_secs(self.timeout), child.wait()).await {
+            Ok(Ok(status)) if status.success() => {
+                info!("Hook `{}` exited with {}", self.command, status);
+            }
+            Ok(Ok(status)) => {
+                warn!("Hook `{}` exited with {}", self.command, status);
+            }
+            Ok(Err(err)) => {
+                error!("Failed waiting for hook `{}` : {}", self.command, err);
+            }
+            Err(_) => {
+                warn!(
+                    "Hook `{}` timed out after {}s, killing it",
+                    self.command, self.timeout
+                );
+                let _ = child.kill().await;
+            }
+        }
+    }
+}
+
+pub struct Hooks {
+    hooks: Vec<Hook>,
+    /// Discovery hooks already run, by index and device address
+    discovered: HashSet<(usize, String)>,
+}
+
+impl Hooks {
+    pub fn new(hooks: Vec<Hook>) -> Hooks {
+        Hooks {
+            hooks,
+            discovered: HashSet::new(),
+        }
+    }
+
+    /// Spawn every hook matching the event, without waiting for them
+    pub fn fire(&self, event: HookEvent, device: &Device) {
+        for hook in self.hooks.iter().filter(|hook| hook.matches(event, device)) {
+            tokio::spawn(hook.clone().run(event, device.clone()));
+        }
+    }
+
+    /// Discovery hooks matching the device that didn't run for it yet
+    fn new_discoveries(&mut self, device: &Device) -> Vec<Hook> {
+        let address = device.address.to_uppercase();
+        let mut hooks = Vec::new();
+        for (index, hook) in self.hooks.iter().enumerate() {
+            if hook.matches(HookEvent::Discovered, device)
+                && self.discovered.insert((index, address.clone()))
+            {
+                hooks.push(hook.clone());
+            }
+        }
+        hooks
+    }
+
+    /// Spawn the discovery hooks matching the device, once per device.
+    /// Called again when the device changes, as its name may only be
+    /// known after it was discovered
+    pub fn fire_discovered(&mut self, device: &Device) {
+        for hook in self.new_discoveries(device) {
+            tokio::spawn(hook.run(HookEvent::Discovered, device.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btleplug::platform::PeripheralId;
+
+    #[derive(Deserialize)]
+    struct Id {
+        periph_id: PeripheralId,
+    }
+
+    fn device(name: &str) -> Device {
+        let id: Id = toml::from_str(
+            "periph_id = { object_path = \"/org/bluez/hci0/dev_AA_BB_CC_DD_EE_FF\" }",
+        )
+        .unwrap();
+        Device {
+            periph_id: id.periph_id,
+            address: "AA:BB:CC:DD:EE:FF".to_string(),
+            name: name.to_string(),
+            alias: None,
+            connected: false,
+            paired: false,
+            trusted: false,
+            rssi: None,
+            tx_power: None,
+        }
+    }
+
+    fn hook(name: Option<&str>) -> Hook {
+        Hook {
+            event: HookEvent::Discovered,
+            address: None,
+            name: name.map(str::to_string),
+            command: "true".to_string(),
+            timeout: 1,
+        }
+    }
+
+    #[test]
+    fn test_pattern_exact() {
+        assert!(pattern_matches("AA:BB:CC:DD:EE:FF", "aa:bb:cc:dd:ee:ff"));
+        assert!(!pattern_matches("AA:BB:CC:DD:EE:FF", "AA:BB:CC:DD:EE:00"));
+    }
+
+    #[test]
+    fn test_pattern_wildcards() {
+        assert!(pattern_matches("*", ""));
+        assert!(pattern_matches("*Bose*", "LE-Bose QC35"));
+        assert!(pattern_matches("AA:BB:*", "AA:BB:CC:DD:EE:FF"));
+        assert!(pattern_matches("Pixel ?", "Pixel 7"));
+        assert!(!pattern_matches("Pixel ?", "Pixel 7a"));
+        assert!(!pattern_matches("*Sony", "Sony WH"));
+    }
+
+    #[test]
+    fn test_discovery_once_the_name_is_known() {
+        let mut hooks = Hooks::new(vec![hook(None), hook(Some("*Bose*"))]);
+
+        assert_eq!(hooks.new_discoveries(&device("Unknown")).len(), 1);
+        let discovered = hooks.new_discoveries(&device("Bose QC35"));
+        assert_eq!(discovered.len(), 1);
+        assert_eq!(discovered[0].name.as_deref(), Some("*Bose*"));
+        assert!(hooks.new_discoveries(&device("Bose QC35")).is_empty());
+    }
+}
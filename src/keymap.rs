@@ -0,0 +1,641 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveDown,
+    MoveUp,
+    Connect,
+    Disconnect,
+    Pair,
+    CancelOperation,
+    Scan,
+    Favourite,
+    Pin,
+    Rename,
+    Help,
+    Palette,
+    GrowList,
+    ShrinkList,
+    GrowDetails,
+    ShrinkDetails,
+    ToggleLogger,
+    Zoom,
+    FocusLogger,
+    LogPageUp,
+    LogPageDown,
+    LogFollow,
+    LogLevelUp,
+    LogLevelDown,
+    LogShowMore,
+    LogShowLess,
+    LogHideOff,
+    LogTargets,
+    LogFullscreen,
+    Quit,
+    Confirm,
+    Cancel,
+}
+
+/// Where a key is pressed. The same key can be bound to different
+/// actions in different contexts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Main,
+    /// The focused logger, falling back to the main keys
+    Logger,
+    Popup,
+}
+
+impl Context {
+    pub const ALL: [Context; 3] = [Context::Main, Context::Logger, Context::Popup];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Context::Main => "Devices list",
+            Context::Logger => "Focused logger",
+            Context::Popup => "Popups",
+        }
+    }
+}
+
+impl Action {
+    pub const ALL: [Action; 32] = [
+        Action::MoveDown,
+        Action::MoveUp,
+        Action::Connect,
+        Action::Disconnect,
+        Action::Pair,
+        Action::CancelOperation,
+        Action::Scan,
+        Action::Favourite,
+        Action::Pin,
+        Action::Rename,
+        Action::Help,
+        Action::Palette,
+        Action::GrowList,
+        Action::ShrinkList,
+        Action::GrowDetails,
+        Action::ShrinkDetails,
+        Action::ToggleLogger,
+        Action::Zoom,
+        Action::FocusLogger,
+        Action::LogPageUp,
+        Action::LogPageDown,
+        Action::LogFollow,
+        Action::LogLevelUp,
+        Action::LogLevelDown,
+        Action::LogShowMore,
+        Action::LogShowLess,
+        Action::LogHideOff,
+        Action::LogTargets,
+        Action::LogFullscreen,
+        Action::Quit,
+        Action::Confirm,
+        Action::Cancel,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveDown => "move_down",
+            Action::MoveUp => "move_up",
+            Action::Connect => "connect",
+            Action::Disconnect => "disconnect",
+            Action::Pair => "pair",
+            Action::CancelOperation => "cancel_operation",
+            Action::Scan => "scan",
+            Action::Favourite => "favourite",
+            Action::Pin => "pin",
+            Action::Rename => "rename",
+            Action::Help => "help",
+            Action::Palette => "palette",
+            Action::GrowList => "grow_list",
+            Action::ShrinkList => "shrink_list",
+            Action::GrowDetails => "grow_details",
+            Action::ShrinkDetails => "shrink_details",
+            Action::ToggleLogger => "toggle_logger",
+            Action::Zoom => "zoom",
+            Action::FocusLogger => "focus_logger",
+            Action::LogPageUp => "log_page_up",
+            Action::LogPageDown => "log_page_down",
+            Action::LogFollow => "log_follow",
+            Action::LogLevelUp => "log_level_up",
+            Action::LogLevelDown => "log_level_down",
+            Action::LogShowMore => "log_show_more",
+            Action::LogShowLess => "log_show_less",
+            Action::LogHideOff => "log_hide_off",
+            Action::LogTargets => "log_targets",
+            Action::LogFullscreen => "log_fullscreen",
+            Action::Quit => "quit",
+            Action::Confirm => "confirm",
+            Action::Cancel => "cancel",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::MoveDown => "move down",
+            Action::MoveUp => "move up",
+            Action::Connect => "connect",
+            Action::Disconnect => "disconnect",
+            Action::Pair => "pair",
+            Action::CancelOperation => "cancel the operations on the device",
+            Action::Scan => "toggle scanning",
+            Action::Favourite => "favourite",
+            Action::Pin => "pin",
+            Action::Rename => "rename",
+            Action::Help => "show keybindings",
+            Action::Palette => "command palette",
+            Action::GrowList => "grow the devices list",
+            Action::ShrinkList => "shrink the devices list",
+            Action::GrowDetails => "grow the details",
+            Action::ShrinkDetails => "shrink the details",
+            Action::ToggleLogger => "show/hide the logger",
+            Action::Zoom => "zoom on the next pane",
+            Action::FocusLogger => "focus/leave the logger",
+            Action::LogPageUp => "scroll the logs up",
+            Action::LogPageDown => "scroll the logs down",
+            Action::LogFollow => "follow the latest logs",
+            Action::LogLevelUp => "log more messages",
+            Action::LogLevelDown => "log fewer messages",
+            Action::LogShowMore => "show more levels of the target",
+            Action::LogShowLess => "show fewer levels of the target",
+            Action::LogHideOff => "show/hide the targets turned off",
+            Action::LogTargets => "show/hide the targets",
+            Action::LogFullscreen => "full screen logs",
+            Action::Quit => "quit",
+            Action::Confirm => "confirm",
+            Action::Cancel => "cancel",
+        }
+    }
+
+    pub fn contexts(&self) -> &'static [Context] {
+        match self {
+            Action::MoveDown | Action::MoveUp => &[Context::Main, Context::Logger, Context::Popup],
+            Action::Confirm => &[Context::Popup],
+            Action::Cancel => &[Context::Logger, Context::Popup],
+            Action::FocusLogger
+            | Action::LogPageUp
+            | Action::LogPageDown
+            | Action::LogFollow
+            | Action::LogLevelUp
+            | Action::LogLevelDown => &[Context::Main, Context::Logger],
+            Action::LogShowMore
+            | Action::LogShowLess
+            | Action::LogHideOff
+            | Action::LogTargets
+            | Action::LogFullscreen => &[Context::Logger],
+            _ => &[Context::Main],
+        }
+    }
+
+    fn default_keys(&self) -> &'static [&'static str] {
+        match self {
+            Action::MoveDown => &["down", "j"],
+            Action::MoveUp => &["up", "k"],
+            Action::Connect => &["c"],
+            Action::Disconnect => &["d"],
+            Action::Pair => &["P"],
+            Action::CancelOperation => &["x"],
+            Action::Scan => &["s"],
+            Action::Favourite => &["f"],
+            Action::Pin => &["p"],
+            Action::Rename => &["r"],
+            Action::Help => &["?"],
+            Action::Palette => &[":"],
+            Action::GrowList => &[">"],
+            Action::ShrinkList => &["<"],
+            Action::GrowDetails => &["+", "="],
+            Action::ShrinkDetails => &["-"],
+            Action::ToggleLogger => &["l"],
+            Action::Zoom => &["z"],
+            Action::FocusLogger => &["tab"],
+            Action::LogPageUp => &["pageup"],
+            Action::LogPageDown => &["pagedown"],
+            Action::LogFollow => &["end"],
+            Action::LogLevelUp => &["]"],
+            Action::LogLevelDown => &["["],
+            Action::LogShowMore => &["right"],
+            Action::LogShowLess => &["left"],
+            Action::LogHideOff => &["space"],
+            Action::LogTargets => &["t"],
+            Action::LogFullscreen => &["F"],
+            Action::Quit => &["q"],
+            Action::Confirm => &["enter"],
+            Action::Cancel => &["esc"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Shift is already part of the character for character keys
+    fn normalize(code: KeyCode, modifiers: KeyModifiers) -> KeyBinding {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        KeyBinding { code, modifiers }
+    }
+
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        *self == KeyBinding::normalize(key.code, key.modifiers)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<KeyBinding, Error> {
+        let invalid = || Error::InvalidKeymap(format!("`{}` is not a valid key", s));
+
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            let lower = rest.to_lowercase();
+            if rest.len() > 1 && lower.starts_with("ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = &rest[5..];
+            } else if rest.len() > 1 && lower.starts_with("alt-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = &rest[4..];
+            } else if rest.len() > 1 && lower.starts_with("shift-") {
+                modifiers |= KeyModifiers::SHIFT;
+                rest = &rest[6..];
+            } else {
+                break;
+            }
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_lowercase().as_str() {
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "space" => KeyCode::Char(' '),
+                "tab" => KeyCode::Tab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                f if f.starts_with('f') => KeyCode::F(f[1..].parse().map_err(|_| invalid())?),
+                _ => return Err(invalid()),
+            },
+        };
+
+        // Shift only changes letters, other characters are written
+        // shifted like `?`
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                if !c.is_alphabetic() || c.to_uppercase().count() != 1 {
+                    return Err(Error::InvalidKeymap(format!(
+                        "`{}` is not a valid key, write the shifted character instead",
+                        s
+                    )));
+                }
+                KeyCode::Char(c.to_uppercase().next().unwrap_or(c))
+            }
+            code => code,
+        };
+
+        Ok(KeyBinding::normalize(code, modifiers))
+    }
+}
+
+impl Display for KeyBinding {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift-")?;
+        }
+        match self.code {
+            KeyCode::Up => write!(f, "↑"),
+            KeyCode::Down => write!(f, "↓"),
+            KeyCode::Left => write!(f, "←"),
+            KeyCode::Right => write!(f, "→"),
+            KeyCode::Enter => write!(f, "↲"),
+            KeyCode::Esc => write!(f, "esc"),
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Tab => write!(f, "tab"),
+            KeyCode::Backspace => write!(f, "backspace"),
+            KeyCode::Delete => write!(f, "delete"),
+            KeyCode::Home => write!(f, "home"),
+            KeyCode::End => write!(f, "end"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Keys of an action in `config.toml`, either `"c"` or `["c", "ctrl-o"]`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Keys {
+    /// Keys bound to `action` when the config file doesn't change them
+    pub fn default_of(action: Action) -> Keys {
+        match action.default_keys() {
+            [key] => Keys::One(key.to_string()),
+            keys => Keys::Many(keys.iter().map(|key| key.to_string()).collect()),
+        }
+    }
+
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Keys::One(key) => vec![key],
+            Keys::Many(keys) => keys,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Action, Vec<KeyBinding>)>,
+}
+
+impl Keymap {
+    /// Build the keymap from the defaults, replacing the keys of every
+    /// action present in `overrides`, keyed by action name
+    pub fn new(overrides: HashMap<String, Keys>) -> Result<Keymap, Error> {
+        let mut overrides = overrides
+            .into_iter()
+            .map(|(name, keys)| match Action::from_name(&name) {
+                Some(action) => Ok((action, keys)),
+                None => Err(Error::InvalidKeymap(format!("unknown action `{}`", name))),
+            })
+            .collect::<Result<HashMap<Action, Keys>, Error>>()?;
+
+        let mut bindings = Vec::new();
+        for action in Action::ALL {
+            let keys = match overrides.remove(&action) {
+                Some(keys) => keys
+                    .into_vec()
+                    .iter()
+                    .map(|key| key.parse())
+                    .collect::<Result<Vec<KeyBinding>, Error>>()?,
+                None => action
+                    .default_keys()
+                    .iter()
+                    .map(|key| key.parse())
+                    .collect::<Result<Vec<KeyBinding>, Error>>()?,
+            };
+            bindings.push((action, keys));
+        }
+
+        let keymap = Keymap { bindings };
+        keymap.check_conflicts()?;
+        Ok(keymap)
+    }
+
+    fn check_conflicts(&self) -> Result<(), Error> {
+        for context in Context::ALL {
+            let mut seen: HashMap<KeyBinding, Action> = HashMap::new();
+            for (action, keys) in self.in_context(context) {
+                for key in keys {
+                    if let Some(other) = seen.insert(*key, *action) {
+                        if other != *action {
+                            return Err(Error::InvalidKeymap(format!(
+                                "`{}` is bound to both {} and {}",
+                                key,
+                                other.name(),
+                                action.name()
+                            )));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn in_context(&self, context: Context) -> impl Iterator<Item = &(Action, Vec<KeyBinding>)> {
+        self.bindings
+            .iter()
+            .filter(move |(action, _)| action.contexts().contains(&context))
+    }
+
+    pub fn action(&self, context: Context, key: &KeyEvent) -> Option<Action> {
+        self.in_context(context)
+            .find(|(_, keys)| keys.iter().any(|binding| binding.matches(key)))
+            .map(|(action, _)| *action)
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyBinding] {
+        self.bindings
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map_or(&[], |(_, keys)| keys.as_slice())
+    }
+
+    /// Keys of an action as shown to the user, like `↓/j`
+    pub fn keys_str(&self, action: Action) -> String {
+        self.keys(action)
+            .iter()
+            .map(|key| key.to_string())
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::new(HashMap::new()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(
+            "ctrl-c".parse::<KeyBinding>().unwrap(),
+            KeyBinding::normalize(KeyCode::Char('c'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            "Alt-Enter".parse::<KeyBinding>().unwrap(),
+            KeyBinding::normalize(KeyCode::Enter, KeyModifiers::ALT)
+        );
+        assert_eq!(
+            "-".parse::<KeyBinding>().unwrap(),
+            KeyBinding::normalize(KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            "f5".parse::<KeyBinding>().unwrap(),
+            KeyBinding::normalize(KeyCode::F(5), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            "shift-q".parse::<KeyBinding>().unwrap(),
+            KeyBinding::normalize(KeyCode::Char('Q'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            "ctrl-shift-q".parse::<KeyBinding>().unwrap(),
+            KeyBinding::normalize(KeyCode::Char('Q'), KeyModifiers::CONTROL)
+        );
+        assert!("shift-1".parse::<KeyBinding>().is_err());
+        assert!("shift-space".parse::<KeyBinding>().is_err());
+        assert!("ctrl-".parse::<KeyBinding>().is_err());
+        assert!("hyper-x".parse::<KeyBinding>().is_err());
+    }
+
+    #[test]
+    fn test_default_keymap() {
+        let keymap = Keymap::default();
+
+        assert_eq!(
+            keymap.action(Context::Main, &key(KeyCode::Char('j'), KeyModifiers::NONE)),
+            Some(Action::MoveDown)
+        );
+        assert_eq!(
+            keymap.action(Context::Popup, &key(KeyCode::Enter, KeyModifiers::NONE)),
+            Some(Action::Confirm)
+        );
+        assert_eq!(
+            keymap.action(Context::Popup, &key(KeyCode::Char('c'), KeyModifiers::NONE)),
+            None
+        );
+        assert_eq!(keymap.keys_str(Action::MoveDown), "↓/j");
+    }
+
+    #[test]
+    fn test_logger_keymap() {
+        let keymap = Keymap::default();
+
+        assert_eq!(
+            keymap.action(Context::Logger, &key(KeyCode::Left, KeyModifiers::NONE)),
+            Some(Action::LogShowLess)
+        );
+        assert_eq!(
+            keymap.action(Context::Logger, &key(KeyCode::Esc, KeyModifiers::NONE)),
+            Some(Action::Cancel)
+        );
+        assert_eq!(
+            keymap.action(Context::Main, &key(KeyCode::Tab, KeyModifiers::NONE)),
+            Some(Action::FocusLogger)
+        );
+        // Main keys are looked up when the logger has none
+        assert_eq!(
+            keymap.action(
+                Context::Logger,
+                &key(KeyCode::Char('c'), KeyModifiers::NONE)
+            ),
+            None
+        );
+        assert_eq!(
+            keymap.action(Context::Main, &key(KeyCode::Char('t'), KeyModifiers::NONE)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_overrides_and_modifiers() {
+        let keymap = Keymap::new(HashMap::from([
+            (
+                "quit".to_string(),
+                Keys::Many(vec!["ctrl-c".into(), "Q".into()]),
+            ),
+            ("connect".to_string(), Keys::One("enter".into())),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            keymap.action(
+                Context::Main,
+                &key(KeyCode::Char('c'), KeyModifiers::CONTROL)
+            ),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            keymap.action(Context::Main, &key(KeyCode::Char('Q'), KeyModifiers::SHIFT)),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            keymap.action(Context::Main, &key(KeyCode::Char('q'), KeyModifiers::NONE)),
+            None
+        );
+        assert_eq!(
+            keymap.action(Context::Main, &key(KeyCode::Char('c'), KeyModifiers::NONE)),
+            None
+        );
+        // Enter confirms in popups and connects in the main view
+        assert_eq!(
+            keymap.action(Context::Main, &key(KeyCode::Enter, KeyModifiers::NONE)),
+            Some(Action::Connect)
+        );
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let err = Keymap::new(HashMap::from([(
+            "connect".to_string(),
+            Keys::One("d".into()),
+        )]))
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("`d` is bound to both connect and disconnect"));
+    }
+
+    #[test]
+    fn test_popup_conflicts() {
+        assert!(Keymap::new(HashMap::from([(
+            "cancel".to_string(),
+            Keys::One("j".into())
+        )]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_unknown_action() {
+        let err = Keymap::new(HashMap::from([(
+            "explode".to_string(),
+            Keys::One("x".into()),
+        )]))
+        .unwrap_err()
+        .to_string();
+
+        assert!(err.contains("unknown action `explode`"));
+    }
+}
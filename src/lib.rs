@@ -1,30 +1,137 @@
-use bluetooth::devices::Devices;
+use aliases::Aliases;
+use bluetooth::{
+    autoconnect::AutoConnect,
+    cache::DeviceCache,
+    devices::{Device, Devices},
+    operations::Operations,
+};
 use events::AppEvent;
-use std::path::PathBuf;
+use keymap::Keymap;
+use std::{path::PathBuf, time::Duration};
 use tokio::sync::mpsc::{Receiver, Sender};
-use ui::widgets::popup::YesNoPopup;
+use tui_logger::TuiWidgetState;
+use ui::{
+    layout::PaneLayout,
+    mouse::{ClickTracker, UiLayout},
+    theme::Theme,
+    widgets::{
+        palette::CommandPalette,
+        popup::{InputPopup, YesNoPopup},
+        toast::Toasts,
+    },
+};
 
+pub mod aliases;
 pub mod bluetooth;
+pub mod commands;
 pub mod events;
+pub mod hooks;
+pub mod keymap;
 pub mod logging;
 pub mod settings;
 pub mod ui;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("Error in BT stuff")]
-    BluetoothError(#[from] btleplug::Error),
+    #[error("Bluetooth error : {}", .0)]
+    BluetoothError(btleplug::Error),
+    #[error("No Bluetooth adapter was found")]
+    NoAdapter,
+    #[error("Adapter {} was not found", .0)]
+    AdapterNotFound(String),
+    /// The device was removed by BlueZ, usually after going out of range
+    #[error("Device {} is gone", .0)]
+    PeripheralGone(String),
+    #[error("Timed out after {:?}", .0)]
+    Timeout(Duration),
+    /// Error returned by BlueZ, like `org.bluez.Error.AuthenticationFailed`
+    #[error("{} : {}", .name.trim_start_matches("org.bluez.Error."), .message)]
+    BluezError { name: String, message: String },
     #[error("Invalid input : {}", .0)]
     InvalidInput(String),
     #[error("Failed parsing the config file at {:?}", .0)]
     InvalidConfigFile(PathBuf),
+    /// Invalid setting of the config file, at a line and column when
+    /// they are known
+    #[error(
+        "Invalid config file {}{} : {}",
+        .path.display(),
+        .position.map(|(line, column)| format!(":{}:{}", line, column)).unwrap_or_default(),
+        .message
+    )]
+    InvalidConfig {
+        path: PathBuf,
+        position: Option<(usize, usize)>,
+        message: String,
+    },
     #[error("IO Error")]
     IOError(#[from] std::io::Error),
+    #[error("Invalid keymap : {}", .0)]
+    InvalidKeymap(String),
+    #[error("Invalid theme : {}", .0)]
+    InvalidTheme(String),
+    #[error("D-Bus error : {}", .0)]
+    DBusError(zbus::Error),
+    #[error("Failed setting up logging : {}", .0)]
+    LoggingError(String),
+    #[error("Invalid recording at line {} : {}", .line, .message)]
+    InvalidRecording { line: usize, message: String },
+    /// Error of a recorded session, as it was shown
+    #[error("{}", .0)]
+    Recorded(String),
+}
+
+impl From<btleplug::Error> for Error {
+    fn from(err: btleplug::Error) -> Error {
+        match err {
+            btleplug::Error::TimedOut(duration) => Error::Timeout(duration),
+            btleplug::Error::Other(other) => match bluetooth::bluez::dbus_error_name(&*other) {
+                Some(name) => Error::BluezError {
+                    name,
+                    message: other.to_string(),
+                },
+                None => Error::BluetoothError(btleplug::Error::Other(other)),
+            },
+            err => Error::BluetoothError(err),
+        }
+    }
+}
+
+impl From<zbus::Error> for Error {
+    fn from(err: zbus::Error) -> Error {
+        match err {
+            zbus::Error::MethodError(name, message, _) if name.starts_with("org.bluez.Error.") => {
+                Error::BluezError {
+                    name: name.to_string(),
+                    message: message.unwrap_or_default(),
+                }
+            }
+            err => Error::DBusError(err),
+        }
+    }
 }
 
 pub struct App {
     pub devices: Devices,
+    pub autoconnect: AutoConnect,
+    pub cache: DeviceCache,
+    pub aliases: Aliases,
+    pub keymap: Keymap,
+    pub theme: Theme,
     pub popup: Option<YesNoPopup>,
+    pub input_popup: Option<InputPopup>,
+    pub palette: Option<CommandPalette>,
+    pub show_help: bool,
+    pub logger_state: TuiWidgetState,
+    /// Keys go to the logger first
+    pub logger_focused: bool,
+    /// Areas of the last frame, for mouse hit-testing
+    pub layout: UiLayout,
+    pub panes: PaneLayout,
+    pub clicks: ClickTracker,
+    pub toasts: Toasts,
+    /// Connections and pairings in progress
+    pub operations: Operations,
     tx: Sender<AppEvent>,
     rx: Receiver<AppEvent>,
 }
@@ -35,12 +142,36 @@ impl App {
         let (tx, rx) = tokio::sync::mpsc::channel(100);
         App {
             devices: Devices::new(),
+            autoconnect: AutoConnect::default(),
+            cache: DeviceCache::default(),
+            aliases: Aliases::default(),
+            keymap: Keymap::default(),
+            theme: Theme::default(),
             popup: None,
+            input_popup: None,
+            palette: None,
+            show_help: false,
+            logger_state: TuiWidgetState::new(),
+            logger_focused: false,
+            layout: UiLayout::default(),
+            panes: PaneLayout::default(),
+            clicks: ClickTracker::default(),
+            toasts: Toasts::default(),
+            operations: Operations::default(),
             tx,
             rx,
         }
     }
 
+    /// Insert or refresh a device in the list, keeping the cache up to date
+    /// with the listed devices
+    pub fn insert_device(&mut self, device: Device) {
+        if self.devices.is_listed(&device) {
+            self.cache.update(&device);
+        }
+        self.devices.insert_or_replace(device);
+    }
+
     pub fn tx(&self) -> Sender<AppEvent> {
         self.tx.clone()
     }
@@ -49,3 +180,31 @@ impl App {
         self.rx.recv().await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_btleplug_errors() {
+        let err = Error::from(btleplug::Error::TimedOut(Duration::from_secs(5)));
+        assert!(matches!(err, Error::Timeout(duration) if duration == Duration::from_secs(5)));
+
+        let err = Error::from(btleplug::Error::Other(Box::new(
+            bluez_async::BluetoothError::DbusError(dbus::Error::new_custom(
+                "org.bluez.Error.AuthenticationFailed",
+                "Authentication Failed",
+            )),
+        )));
+        assert!(
+            matches!(&err, Error::BluezError { name, .. } if name == "org.bluez.Error.AuthenticationFailed")
+        );
+        assert_eq!(
+            err.to_string(),
+            "AuthenticationFailed : Authentication Failed"
+        );
+
+        let err = Error::from(btleplug::Error::NotConnected);
+        assert!(matches!(err, Error::BluetoothError(_)));
+    }
+}
@@ -1,24 +1,606 @@
-use crate::settings::LogSettings;
-use log::info;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-pub fn initialize_logging(log_settings: LogSettings) -> Result<(), Box<dyn std::error::Error>> {
-    tui_logger::init_logger(log_settings.level).unwrap();
-    tui_logger::set_default_level(log_settings.level);
+use log::{
+    error, info,
+    kv::{self, VisitSource},
+    LevelFilter, Log, Metadata, Record,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use slog::Drain;
+use time::{format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime};
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Modifier, Style},
+    widgets::Widget,
+};
+use tui_logger::{TuiLoggerTargetWidget, TuiWidgetState};
+
+use crate::{settings::LogSettings, Error};
+
+static DEFAULT_MAX_FILE_SIZE_MB: u64 = 10;
+static DEFAULT_MAX_FILE_AGE_HOURS: u64 = 24;
+static DEFAULT_MAX_FILES: usize = 10;
+static DEFAULT_MAX_TOTAL_SIZE_MB: u64 = 100;
+/// How often the size and age of the current file are checked
+static ROTATION_CHECK_PERIOD: Duration = Duration::from_secs(10);
+static LATEST_LINK: &str = "latest.log";
+
+/// File the JSON records are written to, when `log_format` is `json`
+static JSON_FILE: Mutex<Option<File>> = Mutex::new(None);
+/// Targets logged so far. tui-logger gives them the default level when
+/// they are first seen and keeps it when the default changes
+static TARGETS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Lines written by tui-logger
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the record
+    Json,
+}
+
+/// Lowest level of the messages that are shown and written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> LevelFilter {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+/// `[logging]` table of the config file
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// A new file is started once the current one is this big
+    pub max_file_size_mb: Option<u64>,
+    /// or this old
+    pub max_file_age_hours: Option<u64>,
+    /// Older files are removed past this number of files
+    pub max_files: Option<usize>,
+    /// or this total size
+    pub max_total_size_mb: Option<u64>,
+    /// Keep a `latest.log` link to the current file
+    pub latest_link: Option<bool>,
+}
+
+impl LoggingConfig {
+    /// Every limit, with the defaults of the unset ones
+    pub fn with_defaults(self) -> LoggingConfig {
+        LoggingConfig {
+            max_file_size_mb: self.max_file_size_mb.or(Some(DEFAULT_MAX_FILE_SIZE_MB)),
+            max_file_age_hours: self.max_file_age_hours.or(Some(DEFAULT_MAX_FILE_AGE_HOURS)),
+            max_files: self.max_files.or(Some(DEFAULT_MAX_FILES)),
+            max_total_size_mb: self.max_total_size_mb.or(Some(DEFAULT_MAX_TOTAL_SIZE_MB)),
+            latest_link: self.latest_link.or(Some(true)),
+        }
+    }
+}
+
+/// Log files of the logs folder, rotated and cleaned up
+#[derive(Debug)]
+pub struct LogFiles {
+    folder: PathBuf,
+    format: LogFormat,
+    max_file_size: u64,
+    max_file_age: Duration,
+    max_files: usize,
+    max_total_size: u64,
+    latest_link: bool,
+    /// File being written and when it was started
+    current: Option<(PathBuf, Instant)>,
+}
+
+/// Name of a log file started at `datetime`, without the colons of
+/// RFC3339 that some filesystems refuse
+fn file_name(datetime: OffsetDateTime) -> Result<String, Error> {
+    datetime
+        .format(format_description!(
+            "[year]-[month]-[day]_[hour]-[minute]-[second].log"
+        ))
+        .map_err(|err| Error::LoggingError(err.to_string()))
+}
+
+fn logging_error(path: &Path, err: std::io::Error) -> Error {
+    Error::LoggingError(format!("{:?} : {}", path, err))
+}
+
+impl LogFiles {
+    pub fn new(folder: PathBuf, format: LogFormat, config: LoggingConfig) -> LogFiles {
+        LogFiles {
+            folder,
+            format,
+            max_file_size: config.max_file_size_mb.unwrap_or(DEFAULT_MAX_FILE_SIZE_MB) << 20,
+            max_file_age: Duration::from_secs(
+                config
+                    .max_file_age_hours
+                    .unwrap_or(DEFAULT_MAX_FILE_AGE_HOURS)
+                    * 3600,
+            ),
+            max_files: config.max_files.unwrap_or(DEFAULT_MAX_FILES).max(1),
+            max_total_size: config
+                .max_total_size_mb
+                .unwrap_or(DEFAULT_MAX_TOTAL_SIZE_MB)
+                << 20,
+            latest_link: config.latest_link.unwrap_or(true),
+            current: None,
+        }
+    }
+
+    /// Path of a new file, numbered when a file was already started
+    /// in the same second
+    fn next_path(&self) -> Result<PathBuf, Error> {
+        let name = file_name(OffsetDateTime::now_utc())?;
+        let mut path = self.folder.join(&name);
+        let mut index = 1;
+        while path.exists() {
+            path = self
+                .folder
+                .join(format!("{}-{}.log", name.trim_end_matches(".log"), index));
+            index += 1;
+        }
+        Ok(path)
+    }
+
+    /// Start writing to a new file, then remove the files past the
+    /// retention limits
+    pub fn start_file(&mut self) -> Result<(), Error> {
+        std::fs::create_dir_all(&self.folder).map_err(|err| logging_error(&self.folder, err))?;
+
+        let path = self.next_path()?;
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| Error::LoggingError(format!("{:?} is not valid UTF-8", path)))?;
+        match self.format {
+            LogFormat::Text => {
+                tui_logger::set_log_file(path_str).map_err(|err| logging_error(&path, err))?
+            }
+            LogFormat::Json => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|err| logging_error(&path, err))?;
+                if let Ok(mut json_file) = JSON_FILE.lock() {
+                    *json_file = Some(file);
+                }
+            }
+        }
+        info!("Logging to file {}", path_str);
+
+        if self.latest_link {
+            self.link_latest(&path)?;
+        }
+        self.current = Some((path, Instant::now()));
+        self.remove_old_files()
+    }
+
+    #[cfg(unix)]
+    fn link_latest(&self, path: &Path) -> Result<(), Error> {
+        let link = self.folder.join(LATEST_LINK);
+        if link.symlink_metadata().is_ok() {
+            std::fs::remove_file(&link).map_err(|err| logging_error(&link, err))?;
+        }
+        // Relative, so that the folder can be moved
+        let target = path.file_name().map_or(path, Path::new);
+        std::os::unix::fs::symlink(target, &link).map_err(|err| logging_error(&link, err))
+    }
+
+    #[cfg(not(unix))]
+    fn link_latest(&self, _path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Start a new file when the current one is too big or too old
+    pub fn rotate(&mut self) -> Result<(), Error> {
+        let Some((path, started)) = &self.current else {
+            return Ok(());
+        };
+
+        let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+        if size >= self.max_file_size || started.elapsed() >= self.max_file_age {
+            self.start_file()?;
+        }
+        Ok(())
+    }
+
+    /// Log files of the folder, newest first
+    fn files(&self) -> Result<Vec<(PathBuf, u64)>, Error> {
+        let entries =
+            std::fs::read_dir(&self.folder).map_err(|err| logging_error(&self.folder, err))?;
+
+        let mut files: Vec<(PathBuf, u64)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name() != LATEST_LINK)
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let path = entry.path();
+                (metadata.is_file() && path.extension()? == "log").then_some((path, metadata.len()))
+            })
+            .collect();
+        // Names start with the date
+        files.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+        Ok(files)
+    }
+
+    /// Remove the oldest files once there are more than `max_files` or
+    /// they take more than `max_total_size`. The current file is kept
+    fn remove_old_files(&self) -> Result<(), Error> {
+        let current = self.current.as_ref().map(|(path, _)| path);
+        let (current_files, old_files): (Vec<_>, Vec<_>) = self
+            .files()?
+            .into_iter()
+            .partition(|(path, _)| Some(path) == current);
+
+        let mut count = current_files.len();
+        let mut total_size: u64 = current_files.iter().map(|(_, size)| size).sum();
+        for (path, size) in old_files {
+            count += 1;
+            total_size += size;
+            if count <= self.max_files && total_size <= self.max_total_size {
+                continue;
+            }
+            if let Err(err) = std::fs::remove_file(&path) {
+                error!("Failed removing the old log file {:?} : {}", path, err);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Write the buffered messages and rotate the log file periodically
+fn spawn_rotation(mut files: LogFiles) {
+    let mut ticker = tokio::time::interval(ROTATION_CHECK_PERIOD);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    tokio::spawn(async move {
+        loop {
+            ticker.tick().await;
+            // Messages are only written to the file when moved out of
+            // the logger buffer, which drawing the logger also does
+            tui_logger::move_events();
+            if let Err(err) = files.rotate() {
+                error!("Failed rotating the log file : {}", err);
+            }
+        }
+    });
+}
+
+/// Fields of a record, like the device address
+struct Fields(Map<String, Value>);
 
-    let mut log_file_path = log_settings.folder.clone();
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value =
+            serde_json::to_value(value).unwrap_or_else(|err| Value::String(err.to_string()));
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
 
-    let datetime = time::OffsetDateTime::now_utc();
-    log_file_path.push(
-        datetime
-            .format(&time::format_description::well_known::Rfc3339)
-            .unwrap(),
+/// JSON object written for `record` in the log file
+fn json_record(record: &Record, timestamp: OffsetDateTime) -> Value {
+    let mut fields = Fields(Map::new());
+    let _ = record.key_values().visit(&mut fields);
+
+    json!({
+        "timestamp": timestamp.format(&Rfc3339).unwrap_or_default(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+        "fields": fields.0,
+    })
+}
+
+/// Hand a record to tui-logger, through the slog drain since its own
+/// logger can't be wrapped
+fn log_to_tui(record: &Record) {
+    let level = match record.level() {
+        log::Level::Error => slog::Level::Error,
+        log::Level::Warn => slog::Level::Warning,
+        log::Level::Info => slog::Level::Info,
+        log::Level::Debug => slog::Level::Debug,
+        log::Level::Trace => slog::Level::Trace,
+    };
+    let location = slog::RecordLocation {
+        file: record.file_static().unwrap_or("?"),
+        line: record.line().unwrap_or(0),
+        column: 0,
+        function: "",
+        module: record.module_path_static().unwrap_or("?"),
+    };
+    let record_static = slog::RecordStatic {
+        location: &location,
+        tag: record.target(),
+        level,
+    };
+
+    let _ = tui_logger::slog_drain().log(
+        &slog::Record::new(&record_static, record.args(), slog::b!()),
+        &slog::OwnedKVList::from(slog::o!()),
     );
-    log_file_path.set_extension("log");
+}
+
+/// Feeds the logger widget, and the JSON log file when there is one
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        // tui-logger filters by target
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if let Ok(mut targets) = TARGETS.lock() {
+            if !targets.contains(record.target()) {
+                targets.insert(record.target().to_string());
+            }
+        }
+        log_to_tui(record);
+
+        if let Ok(mut json_file) = JSON_FILE.lock() {
+            if let Some(file) = json_file.as_mut() {
+                let _ = writeln!(file, "{}", json_record(record, OffsetDateTime::now_utc()));
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut json_file) = JSON_FILE.lock() {
+            if let Some(file) = json_file.as_mut() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+pub fn initialize_logging(log_settings: LogSettings) -> Result<(), Error> {
+    log::set_logger(&Logger).map_err(|err| Error::LoggingError(err.to_string()))?;
+    log::set_max_level(log_settings.level);
+    tui_logger::set_default_level(log_settings.level);
 
     if log_settings.log_to_file {
-        std::fs::create_dir_all(&log_settings.folder)?;
-        tui_logger::set_log_file(log_file_path.to_str().unwrap())?;
-        info!("Logging to file {}", log_file_path.to_str().unwrap());
+        let mut files = LogFiles::new(log_settings.folder, log_settings.format, log_settings.files);
+        files.start_file()?;
+        spawn_rotation(files);
     }
     Ok(())
 }
+
+/// Level of the messages shown for each target by the logger widget of
+/// `state`, without the targets hidden from its selector
+///
+/// The widget keeps them to itself, so they are read back from its
+/// selector drawn off screen, where the shown levels are bold.
+fn shown_levels(state: &TuiWidgetState, targets: usize) -> BTreeMap<String, LevelFilter> {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    let area = Rect::new(0, 0, 256, targets.max(1) as u16);
+    let mut buffer = Buffer::empty(area);
+    TuiLoggerTargetWidget::default()
+        .style_show(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_style(Style::default())
+        .state(state)
+        .render(area, &mut buffer);
+
+    let mut levels = BTreeMap::new();
+    for y in 0..area.height {
+        let target: String = (6..area.width)
+            .map(|x| buffer.get(x, y).symbol.as_str())
+            .collect();
+        let target = target.trim_end();
+        if target.is_empty() {
+            break;
+        }
+        let shown = (0..5)
+            .filter(|&x| buffer.get(x, y).modifier.contains(Modifier::BOLD))
+            .count();
+        levels.insert(target.to_string(), LEVELS[shown]);
+    }
+    levels
+}
+
+/// Change the level of the messages logged while running, and shown
+/// by the logger widget of `state`
+///
+/// Targets the user shows more or less of in the widget keep their level.
+pub fn set_level(level: LevelFilter, state: &mut TuiWidgetState) {
+    let previous = log::max_level();
+    let targets = TARGETS
+        .lock()
+        .map(|targets| targets.clone())
+        .unwrap_or_default();
+    let shown = shown_levels(state, targets.len());
+
+    log::set_max_level(level);
+    tui_logger::set_default_level(level);
+    for target in targets {
+        tui_logger::set_level_for_target(&target, level);
+        // The widget only lowers the levels it shows on its own
+        if shown.get(&target) == Some(&previous) {
+            *state = std::mem::take(state).set_level_for_target(&target, level);
+        }
+    }
+}
+
+/// Level next to `level`, logging more messages when `more` is set
+pub fn next_level(level: LevelFilter, more: bool) -> LevelFilter {
+    let index = level as usize;
+    let index = if more {
+        index + 1
+    } else {
+        index.saturating_sub(1)
+    };
+    LevelFilter::iter().nth(index).unwrap_or(LevelFilter::max())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use time::macros::datetime;
+
+    fn temp_folder() -> PathBuf {
+        let mut folder = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        folder.push("temp");
+        folder.push(format!("logs-{}", rand::thread_rng().gen_range(0..100000)));
+        std::fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    fn names(folder: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(
+            file_name(datetime!(2023-02-05 09:04:31 UTC)).unwrap(),
+            "2023-02-05_09-04-31.log"
+        );
+    }
+
+    #[test]
+    fn test_json_record() {
+        let fields: &[(&str, &str)] = &[("address", "AA:BB:CC:DD:EE:FF"), ("event", "connected")];
+        let json = json_record(
+            &Record::builder()
+                .args(format_args!("Connected to {}", "Headphones"))
+                .level(log::Level::Info)
+                .target("bltui")
+                .key_values(&fields)
+                .build(),
+            datetime!(2023-02-05 09:04:31 UTC),
+        );
+
+        assert_eq!(
+            json,
+            json!({
+                "timestamp": "2023-02-05T09:04:31Z",
+                "level": "INFO",
+                "target": "bltui",
+                "message": "Connected to Headphones",
+                "fields": {
+                    "address": "AA:BB:CC:DD:EE:FF",
+                    "event": "connected",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_retention() {
+        let folder = temp_folder();
+        for (name, size) in [
+            ("2023-01-01_00-00-00.log", 10),
+            ("2023-01-02_00-00-00.log", 10),
+            ("2023-01-03_00-00-00.log", 10),
+            ("2023-01-04_00-00-00.log", 10),
+            ("notes.txt", 10),
+        ] {
+            std::fs::write(folder.join(name), vec![b'a'; size]).unwrap();
+        }
+
+        let mut files = LogFiles::new(
+            folder.clone(),
+            LogFormat::Text,
+            LoggingConfig {
+                max_files: Some(3),
+                ..LoggingConfig::default()
+            },
+        );
+        // The current file is kept even when it is the oldest
+        files.current = Some((folder.join("2023-01-01_00-00-00.log"), Instant::now()));
+        files.remove_old_files().unwrap();
+        assert_eq!(
+            names(&folder),
+            [
+                "2023-01-01_00-00-00.log",
+                "2023-01-03_00-00-00.log",
+                "2023-01-04_00-00-00.log",
+                "notes.txt"
+            ]
+        );
+
+        files.max_total_size = 25;
+        files.remove_old_files().unwrap();
+        let remaining = names(&folder);
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(
+            remaining,
+            [
+                "2023-01-01_00-00-00.log",
+                "2023-01-04_00-00-00.log",
+                "notes.txt"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_start_file_links_latest() {
+        let folder = temp_folder().join("logs");
+        let mut files = LogFiles::new(folder.clone(), LogFormat::Text, LoggingConfig::default());
+
+        files.start_file().unwrap();
+        files.start_file().unwrap();
+        let (current, _) = files.current.clone().unwrap();
+        let link = std::fs::read_link(folder.join(LATEST_LINK)).unwrap();
+        let count = names(&folder).len();
+        std::fs::remove_dir_all(folder.parent().unwrap()).unwrap();
+
+        assert_eq!(link, current.file_name().map(PathBuf::from).unwrap());
+        // Two files and the link
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_next_level() {
+        assert_eq!(next_level(LevelFilter::Info, true), LevelFilter::Debug);
+        assert_eq!(next_level(LevelFilter::Info, false), LevelFilter::Warn);
+        assert_eq!(next_level(LevelFilter::Trace, true), LevelFilter::Trace);
+        assert_eq!(next_level(LevelFilter::Off, false), LevelFilter::Off);
+    }
+}
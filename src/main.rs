@@ -1,68 +1,460 @@
 use std::time::Duration;
 
 use bltui::{
+    aliases::Aliases,
     bluetooth::{
         agent::{Agent, AgentCapability},
+        autoconnect::AutoConnect,
+        cache::DeviceCache,
         controller::BluetoothController,
+        devices::Device,
+        manager::{Manager, ManagerError},
+        operations::{Operation, SPINNER_RATE},
+        pins::Pins,
     },
+    commands::Command,
     events::{
-        adapter::spawn_adapter_watcher, agent::AgentEvent, keys::spawn_keypress_watcher,
-        tick::spawn_ticker, AppEvent,
+        adapter::{spawn_adapter_watcher, AdapterEvents, DeviceEvent},
+        agent::AgentEvent,
+        autoconnect::{spawn_connect_attempt, AutoConnectEvent},
+        config::spawn_config_watcher,
+        keys::spawn_input_watcher,
+        manager::ManagerEvent,
+        operation::{spawn_operation, OperationEvent},
+        record::{read_recording, spawn_replay, Recorder},
+        signals::spawn_signal_watcher,
+        tick::{spawn_frame_scheduler, spawn_refresh_timer},
+        AppEvent,
     },
-    logging::initialize_logging,
+    hooks::{HookEvent, Hooks},
+    keymap::{Action, Context, Keymap},
+    logging::{initialize_logging, next_level, set_level},
     settings::AppSettings,
-    ui::{draw_frame, initialize_terminal, widgets::popup::YesNoPopup},
+    ui::{
+        draw_frame, initialize_terminal,
+        layout::{Pane, PaneLayout, RESIZE_STEP},
+        terminal::{terminal_restored, TerminalGuard},
+        theme::Theme,
+        widgets::{
+            palette::CommandPalette,
+            popup::{InputPopup, InputPurpose, YesNoPopup},
+        },
+    },
     App,
 };
-use btleplug::api::CentralEvent;
-use crossterm::{
-    event::KeyCode,
-    execute,
-    terminal::{disable_raw_mode, LeaveAlternateScreen},
-};
+use btleplug::{api::CentralEvent, platform::PeripheralId};
+use crossterm::event::{KeyCode, MouseButton, MouseEventKind};
 use log::{debug, error, info, trace, warn};
+use tokio::task::JoinHandle;
+use tui_logger::TuiWidgetEvent;
+use zbus::Connection;
 
 #[macro_use]
 extern crate lazy_static;
 
 lazy_static! {
-    static ref TICK_RATE: Duration = Duration::from_millis(16);
-    static ref KEY_POLL_RATE: Duration = Duration::from_millis(8);
+    static ref LOG_REFRESH_RATE: Duration = Duration::from_secs(1);
+    static ref CONFIG_POLL_RATE: Duration = Duration::from_secs(1);
+}
+
+/// Set the alias through BlueZ when possible, locally otherwise.
+/// An empty alias goes back to the advertised name
+async fn rename_device(
+    app: &mut App,
+    controller: Option<&BluetoothController>,
+    periph_id: PeripheralId,
+    address: String,
+    alias: String,
+) {
+    let renamed = match controller {
+        Some(controller) => controller.set_alias(&periph_id, &alias).await,
+        None => Ok(false),
+    };
+    match renamed {
+        Ok(true) => {
+            info!(
+                address = address.as_str(), event = "renamed";
+                "Renamed {} to {} in BlueZ", address, alias
+            );
+        }
+        Ok(false) => {
+            if let Err(err) = app.aliases.set(&address, &alias) {
+                error!("Failed saving the alias of {} : {}", address, err);
+            } else {
+                info!(
+                    address = address.as_str(), event = "renamed";
+                    "Renamed {} to {} locally", address, alias
+                );
+            }
+        }
+        Err(err) => {
+            report_error(app, &format!("Failed renaming {}", address), &err);
+            return;
+        }
+    }
+
+    if let Some(device) = app.devices.get_by_address(&address) {
+        let mut device = device.clone();
+        device.alias = if alias.is_empty() {
+            app.aliases.get(&address).cloned()
+        } else {
+            Some(alias)
+        };
+        app.insert_device(device);
+    }
+}
+
+/// Replace the controller with one using the adapter `name`, returning
+/// the events of the new adapter
+async fn switch_adapter(
+    controller: &mut BluetoothController,
+    name: &str,
+    connection: Connection,
+) -> Result<AdapterEvents, bltui::Error> {
+    let mut new_controller = BluetoothController::from_adapter(name).await?;
+    new_controller.set_bluez_connection(connection);
+    let events = new_controller.events().await?;
+
+    if controller.scanning {
+        controller.trigger_scan().await?;
+    }
+    *controller = new_controller;
+
+    Ok(events)
+}
+
+/// Log an error and show it in a toast
+fn report_error(app: &mut App, action: &str, err: &bltui::Error) {
+    error!("{} : {}", action, err);
+    app.toasts.error(format!("{} : {}", action, err));
+}
+
+/// Warn that `action` needs Bluetooth, which a replayed session doesn't
+/// have
+fn not_replayable(app: &mut App, action: &str) {
+    warn!("{} is not available while replaying", action);
+    app.toasts
+        .warning(format!("{} is not available while replaying", action));
+}
+
+/// The device behind an adapter event, which may already be gone
+async fn lookup_device(
+    app: &App,
+    controller: &BluetoothController,
+    periph_id: &PeripheralId,
+) -> Option<Device> {
+    match controller.get_device(periph_id).await {
+        Ok(device) => Some(app.aliases.apply(device)),
+        Err(err) => {
+            debug!("Ignoring the event of {} : {}", periph_id, err);
+            None
+        }
+    }
+}
+
+/// Resolve an adapter event into the device it is about
+async fn device_event(
+    app: &App,
+    controller: &BluetoothController,
+    event: CentralEvent,
+) -> Option<DeviceEvent> {
+    let (periph_id, device_event): (PeripheralId, fn(Device) -> DeviceEvent) = match event {
+        CentralEvent::DeviceDiscovered(periph_id) => (periph_id, DeviceEvent::Discovered),
+        CentralEvent::DeviceConnected(periph_id) => (periph_id, DeviceEvent::Connected),
+        CentralEvent::DeviceDisconnected(periph_id) => (periph_id, DeviceEvent::Disconnected),
+        CentralEvent::DeviceUpdated(periph_id) => (periph_id, DeviceEvent::Updated),
+        _ => return None,
+    };
+
+    lookup_device(app, controller, &periph_id)
+        .await
+        .map(device_event)
+}
+
+/// Start an operation on a device, unless it is already running.
+/// Without a controller the operation waits for its recorded result
+fn start_operation(
+    app: &mut App,
+    controller: Option<&BluetoothController>,
+    device: &Device,
+    operation: Operation,
+) {
+    let name = device.display_name().to_string();
+    if app.operations.is_running(&device.address, operation) {
+        info!("Already {} {}", operation.progress(), name);
+        return;
+    }
+
+    let controller = controller.cloned();
+    let periph_id = device.periph_id.clone();
+    let address = device.address.clone();
+    let tx = app.tx();
+    app.operations.start(
+        &device.address,
+        name.clone(),
+        operation,
+        |id| match controller {
+            Some(controller) => spawn_operation(
+                id,
+                address,
+                name,
+                operation,
+                async move {
+                    match operation {
+                        Operation::Connect => controller.connect(&periph_id).await,
+                        Operation::Disconnect => controller.disconnect(&periph_id).await,
+                        Operation::Pair => controller.pair(&periph_id).await,
+                    }
+                },
+                tx,
+            ),
+            None => tokio::spawn(std::future::pending()),
+        },
+    );
+}
+
+/// Connect to a favourite after `delay`. The connection is reported to
+/// auto-connect with `report_connect_attempt` when it ends
+fn schedule_connect_attempt(app: &mut App, address: &str, delay: Duration) {
+    let task = spawn_connect_attempt(address.to_string(), delay, app.tx());
+    app.autoconnect.track_attempt(address, task);
+}
+
+/// Tell auto-connect how a connection to `address` ended, retrying
+/// later when it failed
+fn report_connect_attempt(app: &mut App, address: &str, error: Option<String>) {
+    if !app.autoconnect.is_attempting(address) {
+        return;
+    }
+    if let Some(delay) = app.autoconnect.on_attempt(address, error) {
+        info!(
+            address = address, event = "reconnecting";
+            "Retrying to connect to {} in {:?}", address, delay
+        );
+        schedule_connect_attempt(app, address, delay);
+    }
+}
+
+/// Ask BlueZ to stop pairing with a device, in the background
+fn cancel_pairing(app: &App, controller: Option<&BluetoothController>, address: &str) {
+    let (Some(controller), Some(device)) = (controller, app.devices.get_by_address(address)) else {
+        return;
+    };
+
+    let controller = controller.clone();
+    let periph_id = device.periph_id.clone();
+    tokio::spawn(async move {
+        // Fails when BlueZ was not pairing, which is fine
+        if let Err(err) = controller.cancel_pairing(&periph_id).await {
+            debug!("Did not cancel pairing with {} : {}", periph_id, err);
+        }
+    });
+}
+
+/// Read a device again after a change BlueZ may not send an event for
+async fn refresh_device(app: &mut App, controller: Option<&BluetoothController>, address: &str) {
+    let Some(controller) = controller else {
+        return;
+    };
+    let Some(periph_id) = app
+        .devices
+        .get_by_address(address)
+        .map(|device| device.periph_id.clone())
+    else {
+        return;
+    };
+
+    if let Some(device) = lookup_device(app, controller, &periph_id).await {
+        app.insert_device(device);
+    }
+}
+
+fn save_cache(app: &App) {
+    if let Err(err) = app.cache.save() {
+        error!("Failed saving the device cache : {}", err);
+    }
+}
+
+/// Give the keys back to the devices list, leaving the full screen logs
+fn leave_logger(app: &mut App) {
+    app.logger_focused = false;
+    if app.panes.zoom == Some(Pane::Logger) {
+        app.panes.zoom = None;
+    }
+}
+
+fn save_layout(app: &App) {
+    if let Err(err) = app.panes.save() {
+        error!("Failed saving the layout : {}", err);
+    }
+}
+
+/// Everything talking to BlueZ, missing when replaying a session
+struct Bluetooth {
+    controller: BluetoothController,
+    agent: Agent<'static>,
+    manager: Manager<'static>,
+    adapter_watcher: JoinHandle<()>,
+}
+
+impl Bluetooth {
+    /// Open the adapter, register the agent and the manager, and list
+    /// the paired devices
+    async fn start(app: &mut App, adapter: Option<String>) -> Result<Bluetooth, bltui::Error> {
+        let mut controller = if let Some(adapter) = adapter {
+            BluetoothController::from_adapter(&adapter).await?
+        } else {
+            BluetoothController::from_first_adapter().await?
+        };
+
+        let agent = Agent::initialize_dbus_connection(
+            "/bltui/agent".into(),
+            AgentCapability::KeyboardDisplay,
+        )
+        .await?;
+        agent.start_server(app.tx()).await?;
+        agent.request_name("bltui.agent").await?;
+        agent.register().await?;
+        agent.request_default().await?;
+
+        controller.set_bluez_connection(agent.connection());
+        match controller.get_paired_devices().await {
+            Ok(devices) => {
+                for device in devices {
+                    app.insert_device(app.aliases.apply(device));
+                }
+            }
+            Err(err) => report_error(app, "Failed listing paired devices", &err),
+        }
+
+        let manager = Manager::new(agent.connection(), "/bltui/manager".into());
+        manager.start_server(app.tx()).await;
+
+        let adapter_watcher = spawn_adapter_watcher(controller.events().await?, app.tx()).await;
+
+        Ok(Bluetooth {
+            controller,
+            agent,
+            manager,
+            adapter_watcher,
+        })
+    }
+}
+
+fn controller(bluetooth: &Option<Bluetooth>) -> Option<&BluetoothController> {
+    bluetooth.as_ref().map(|bluetooth| &bluetooth.controller)
+}
+
+fn scanning(bluetooth: &Option<Bluetooth>) -> bool {
+    bluetooth
+        .as_ref()
+        .is_some_and(|bluetooth| bluetooth.controller.scanning)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let settings = AppSettings::parse();
+    let settings = match AppSettings::parse() {
+        Ok(Some(settings)) => settings,
+        Ok(None) => return Ok(()),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
     initialize_logging(settings.log_settings)?;
 
+    let replay = match &settings.replay {
+        Some(path) => Some(read_recording(path)?),
+        None => None,
+    };
+
     let mut app = App::new();
+    app.keymap = Keymap::new(settings.live.keys)?;
+    app.theme = Theme::new(settings.live.theme, settings.live.no_color)?;
+    app.devices.show_unknown = settings.live.show_unknown;
 
-    let mut bt_controller = if let Some(adapter) = settings.adapter {
-        BluetoothController::from_adapter(&adapter)
-            .await
-            .expect("The requested adapter was not found")
+    // A replayed session starts from scratch and saves nothing
+    let mut hooks = if replay.is_some() {
+        Hooks::new(Vec::new())
     } else {
-        BluetoothController::from_first_adapter().await
+        app.panes = PaneLayout::new(settings.layout, settings.config_file.clone());
+        app.autoconnect = AutoConnect::new(settings.favourites, Some(settings.config_file.clone()));
+        app.devices.favourites = app.autoconnect.favourites();
+        app.devices.pins = Pins::load(settings.pins_file);
+        app.aliases = Aliases::new(settings.aliases, settings.config_file);
+        app.cache = DeviceCache::load(settings.cache_file);
+        for cached in app.cache.startup_devices() {
+            app.devices
+                .insert_or_replace(app.aliases.apply(cached.to_device()));
+        }
+        Hooks::new(settings.hooks)
     };
 
-    let agent =
-        Agent::initialize_dbus_connection("/bltui/agent".into(), AgentCapability::KeyboardDisplay)
-            .await;
-    agent.start_server(app.tx()).await;
-    agent.request_name("bltui.agent").await;
-    agent.register().await;
-    agent.request_default().await;
+    let mut bluetooth = if replay.is_some() {
+        None
+    } else {
+        Some(Bluetooth::start(&mut app, settings.adapter).await?)
+    };
 
-    spawn_ticker(*TICK_RATE, app.tx());
+    let frames = spawn_frame_scheduler(settings.max_fps, app.tx());
+    spawn_refresh_timer(*LOG_REFRESH_RATE, frames.clone());
 
-    spawn_keypress_watcher(app.tx());
+    spawn_input_watcher(app.tx());
+    spawn_signal_watcher(app.tx());
+    spawn_config_watcher(
+        settings.reloader.path().to_path_buf(),
+        *CONFIG_POLL_RATE,
+        app.tx(),
+    );
 
-    spawn_adapter_watcher(bt_controller.events().await?, app.tx()).await;
+    if let Some(entries) = replay {
+        info!(
+            "Replaying {} events at {}x speed",
+            entries.len(),
+            settings.replay_speed
+        );
+        spawn_replay(entries, settings.replay_speed, app.tx());
+    }
+    let mut recorder = match &settings.record {
+        Some(path) => Some(Recorder::create(path)?),
+        None => None,
+    };
+
+    let terminal_guard = TerminalGuard::new(settings.mouse, app.tx());
+    let mut terminal = initialize_terminal(settings.mouse)?;
 
-    let mut terminal = initialize_terminal()?;
+    let mut panicked = false;
+    while let Some(mut event) = app.events().await {
+        // The panic hook restored the terminal, nothing can be drawn
+        if matches!(event, AppEvent::Panicked) || terminal_restored() {
+            error!("Shutting down after a panic");
+            panicked = true;
+            break;
+        }
+        // Devices are read right away, so that recordings have them
+        if let AppEvent::Adapter(central_event) = event {
+            trace!("Received adapter event : {:?}", central_event);
+            let Some(bluetooth) = &bluetooth else {
+                continue;
+            };
+            match device_event(&app, &bluetooth.controller, central_event).await {
+                Some(device_event) => event = AppEvent::Device(device_event),
+                None => continue,
+            }
+        }
+        if let Some(session) = &mut recorder {
+            if let Err(err) = session.record(&event) {
+                report_error(&mut app, "Stopped recording the session", &err);
+                recorder = None;
+            }
+        }
 
-    while let Some(event) = app.events().await {
+        let mut command = None;
+        // Anything but a frame can change what is shown
+        if !matches!(event, AppEvent::Tick) {
+            frames.request();
+        }
         match event {
             AppEvent::Agent(ev) => {
                 debug!("Received Agent event : {:?}", ev);
@@ -88,13 +480,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     AgentEvent::Release { tx } => {
                         error!("Agent release was requested, shutting down");
                         std::thread::sleep(Duration::from_secs(5));
-                        tx.send(Ok(())).unwrap();
+                        let _ = tx.send(Ok(()));
                         break;
                     }
                     AgentEvent::Cancel { tx } => {
                         warn!("Pairing cancelled");
                         app.popup = None;
-                        tx.send(Ok(())).unwrap();
+                        let _ = tx.send(Ok(()));
                     }
                     AgentEvent::RequestAuthorization { tx } => {
                         app.popup = Some(YesNoPopup::new(
@@ -103,116 +495,648 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         ));
                     }
                     AgentEvent::RequestPasskey { tx } => {
-                        tx.send(Ok(0_u32)).unwrap();
+                        let _ = tx.send(Ok(0_u32));
                     }
                     AgentEvent::RequestPincode { tx } => {
-                        tx.send(Ok(String::from("wontwork"))).unwrap();
+                        let _ = tx.send(Ok(String::from("wontwork")));
                     }
                 }
             }
-            AppEvent::Adapter(ev) => {
-                trace!("Received adapter event : {:?}", ev);
-                match ev {
-                    CentralEvent::DeviceDiscovered(periph_id) => {
-                        debug!("Device discovered");
-                        let device = bt_controller.get_device(&periph_id).await;
-                        if device.name != "Unknown" || settings.show_unknown {
-                            app.devices.insert_or_replace(device);
+            // Resolved into device events above
+            AppEvent::Adapter(_) => {}
+            AppEvent::Device(ev) => match ev {
+                DeviceEvent::Discovered(device) => {
+                    debug!("Device discovered");
+                    if let Some(delay) = app
+                        .autoconnect
+                        .on_discovered(&device.address, device.connected)
+                    {
+                        schedule_connect_attempt(&mut app, &device.address, delay);
+                    }
+                    if app.devices.is_listed(&device) {
+                        if let Some(bluetooth) = &bluetooth {
+                            bluetooth.manager.device_found(&device).await;
                         }
+                        hooks.fire_discovered(&device);
                     }
-                    CentralEvent::DeviceConnected(periph_id) => {
-                        info!("Connected to");
-                        let device = bt_controller.get_device(&periph_id).await;
-                        if device.name != "Unknown" || settings.show_unknown {
-                            app.devices.insert_or_replace(device);
+                    app.insert_device(device);
+                }
+                DeviceEvent::Connected(device) => {
+                    info!(
+                        address = device.address.as_str(), event = "connected";
+                        "Connected to {}", device.display_name()
+                    );
+                    if app.operations.finish(&device.address, Operation::Connect) {
+                        app.toasts
+                            .success(format!("Connected to {}", device.display_name()));
+                    }
+                    report_connect_attempt(&mut app, &device.address, None);
+                    if let Some(bluetooth) = &bluetooth {
+                        bluetooth.manager.device_connected(&device).await;
+                    }
+                    hooks.fire(HookEvent::Connected, &device);
+                    app.insert_device(device);
+                    save_cache(&app);
+                }
+                DeviceEvent::Disconnected(device) => {
+                    info!(
+                        address = device.address.as_str(), event = "disconnected";
+                        "Disconnected from {}", device.display_name()
+                    );
+                    if app
+                        .operations
+                        .finish(&device.address, Operation::Disconnect)
+                    {
+                        app.toasts
+                            .success(format!("Disconnected from {}", device.display_name()));
+                    }
+                    hooks.fire(HookEvent::Disconnected, &device);
+                    if let Some(delay) = app.autoconnect.on_disconnected(&device.address) {
+                        info!(
+                            address = device.address.as_str(), event = "reconnecting";
+                            "Reconnecting to {} in {:?}", device.name, delay
+                        );
+                        schedule_connect_attempt(&mut app, &device.address, delay);
+                    }
+                    app.insert_device(device);
+                    save_cache(&app);
+                }
+                DeviceEvent::Updated(device) => {
+                    if app.devices.is_listed(&device) {
+                        hooks.fire_discovered(&device);
+                    }
+                    app.insert_device(device);
+                }
+            },
+            AppEvent::Manager(ev) => {
+                debug!("Received Manager event : {:?}", ev);
+                let Some(bluetooth) = &mut bluetooth else {
+                    continue;
+                };
+                match ev {
+                    ManagerEvent::Connect { address, tx } => {
+                        if let Some(device) = app.devices.get_by_address(&address).cloned() {
+                            app.autoconnect.resume(&address);
+                            start_operation(
+                                &mut app,
+                                Some(&bluetooth.controller),
+                                &device,
+                                Operation::Connect,
+                            );
+                            app.operations.wait(&address, Operation::Connect, tx);
+                        } else {
+                            let _ = tx.send(Err(ManagerError::UnknownDevice(address)));
                         }
                     }
-                    CentralEvent::DeviceDisconnected(periph_id) => {
-                        info!("Disconnected from ");
-                        let device = bt_controller.get_device(&periph_id).await;
-                        if device.name != "Unknown" || settings.show_unknown {
-                            app.devices.insert_or_replace(device);
+                    ManagerEvent::Disconnect { address, tx } => {
+                        if let Some(device) = app.devices.get_by_address(&address).cloned() {
+                            app.autoconnect.pause(&address);
+                            start_operation(
+                                &mut app,
+                                Some(&bluetooth.controller),
+                                &device,
+                                Operation::Disconnect,
+                            );
+                            app.operations.wait(&address, Operation::Disconnect, tx);
+                        } else {
+                            let _ = tx.send(Err(ManagerError::UnknownDevice(address)));
                         }
                     }
-                    CentralEvent::DeviceUpdated(periph_id) => {
-                        let device = bt_controller.get_device(&periph_id).await;
-                        if device.name != "Unknown" || settings.show_unknown {
-                            app.devices.insert_or_replace(device);
+                    ManagerEvent::Scan { enable, tx } => {
+                        let result = if bluetooth.controller.scanning != enable {
+                            bluetooth
+                                .controller
+                                .trigger_scan()
+                                .await
+                                .map_err(|err| ManagerError::Failed(err.to_string()))
+                        } else {
+                            Ok(())
+                        };
+                        let _ = tx.send(result);
+                    }
+                }
+            }
+            AppEvent::AutoConnect(ev) => {
+                debug!("Received AutoConnect event : {:?}", ev);
+                match ev {
+                    AutoConnectEvent::Due { address } => {
+                        app.autoconnect.on_due(&address);
+                        if let Some(device) = app.devices.get_by_address(&address).cloned() {
+                            info!(
+                                address = address.as_str(), event = "autoconnecting";
+                                "Auto-connecting to {}", device.display_name()
+                            );
+                            // Joins the connection the user may have started
+                            start_operation(
+                                &mut app,
+                                controller(&bluetooth),
+                                &device,
+                                Operation::Connect,
+                            );
+                        } else {
+                            let error = "the device is not known".to_string();
+                            report_connect_attempt(&mut app, &address, Some(error));
                         }
                     }
-                    _ => {}
                 }
             }
             AppEvent::Input(key) => {
                 debug!("Received input key : {:?}", key);
                 if let Some(popup) = &mut app.popup {
-                    match key.code {
-                        KeyCode::Down | KeyCode::Char('j') => {
+                    match app.keymap.action(Context::Popup, &key) {
+                        Some(Action::MoveDown) => {
                             popup.move_selector_down();
                         }
-                        KeyCode::Up | KeyCode::Char('k') => {
+                        Some(Action::MoveUp) => {
                             popup.move_selector_up();
                         }
-                        KeyCode::Enter => {
+                        Some(Action::Confirm) => {
                             popup.confirm();
                             app.popup = None;
                         }
+                        Some(Action::Cancel) => {
+                            popup.cancel();
+                            app.popup = None;
+                        }
                         _ => {}
                     }
-                } else {
-                    match key.code {
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            app.devices.move_selector_down();
-                        }
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            app.devices.move_selector_up();
-                        }
-                        KeyCode::Char('c') => {
-                            let controller = bt_controller.clone();
-                            let device_opt = &app.devices.get_selected_device().await;
-                            if let Some(device) = device_opt {
-                                let periph_id = device.periph_id.clone();
-                                tokio::spawn(async move {
-                                    controller.connect(&periph_id).await.unwrap();
-                                });
+                } else if let Some(input_popup) = &mut app.input_popup {
+                    match (app.keymap.action(Context::Popup, &key), key.code) {
+                        (Some(Action::Cancel), _) => {
+                            app.input_popup = None;
+                        }
+                        (Some(Action::Confirm), _) => {
+                            let value = input_popup.value().to_string();
+                            let purpose = input_popup.purpose.clone();
+                            app.input_popup = None;
+                            match purpose {
+                                InputPurpose::Rename { periph_id, address } => {
+                                    rename_device(
+                                        &mut app,
+                                        controller(&bluetooth),
+                                        periph_id,
+                                        address,
+                                        value,
+                                    )
+                                    .await;
+                                }
                             }
                         }
-                        KeyCode::Char('d') => {
-                            let controller = bt_controller.clone();
-                            let device_opt = &app.devices.get_selected_device().await;
-                            if let Some(device) = device_opt {
-                                let periph_id = device.periph_id.clone();
-                                tokio::spawn(async move {
-                                    controller.disconnect(&periph_id).await.unwrap();
-                                });
+                        (_, KeyCode::Char(c)) => {
+                            input_popup.push(c);
+                        }
+                        (_, KeyCode::Backspace) => {
+                            input_popup.pop();
+                        }
+                        _ => {}
+                    }
+                } else if let Some(palette) = &mut app.palette {
+                    match (app.keymap.action(Context::Popup, &key), key.code) {
+                        (Some(Action::Cancel), _) => {
+                            app.palette = None;
+                        }
+                        (Some(Action::Confirm), _) => {
+                            match palette.command() {
+                                Ok(palette_command) => command = Some(palette_command),
+                                Err(err) => error!("{}", err),
                             }
+                            app.palette = None;
                         }
-                        KeyCode::Char('s') => {
-                            bt_controller.trigger_scan().await?;
+                        (_, KeyCode::Down) => {
+                            palette.move_selector_down();
                         }
-                        KeyCode::Char('q') => {
-                            break;
+                        (_, KeyCode::Up) => {
+                            palette.move_selector_up();
+                        }
+                        (_, KeyCode::Char(c)) => {
+                            palette.push(c);
+                        }
+                        (_, KeyCode::Backspace) => {
+                            palette.pop();
                         }
                         _ => {}
                     }
+                } else if app.show_help {
+                    app.show_help = false;
+                } else if app.logger_focused {
+                    command = app
+                        .keymap
+                        .action(Context::Logger, &key)
+                        .or_else(|| app.keymap.action(Context::Main, &key))
+                        .map(Command::Action);
+                } else {
+                    command = app.keymap.action(Context::Main, &key).map(Command::Action);
+                }
+            }
+            AppEvent::Mouse(mouse) => {
+                trace!("Received mouse event : {:?}", mouse);
+                let (column, row) = (mouse.column, mouse.row);
+                match mouse.kind {
+                    MouseEventKind::Down(MouseButton::Left) => {
+                        if let Some(popup) = &mut app.popup {
+                            let option = app
+                                .layout
+                                .popup
+                                .and_then(|area| popup.option_at(area, column, row));
+                            if let Some(option) = option {
+                                popup.select(option);
+                                popup.confirm();
+                                app.popup = None;
+                            }
+                        } else if app.show_help {
+                            app.show_help = false;
+                        } else if app.input_popup.is_none() && app.palette.is_none() {
+                            if let Some(index) =
+                                app.layout.device_row(column, row, app.devices.offset())
+                            {
+                                let double_click = app.clicks.click(column, row);
+                                if app.devices.select_row(index) && double_click {
+                                    command = Some(Command::Action(Action::Connect));
+                                }
+                            }
+                        }
+                    }
+                    MouseEventKind::ScrollDown | MouseEventKind::ScrollUp
+                        if app.popup.is_none() =>
+                    {
+                        let down = mouse.kind == MouseEventKind::ScrollDown;
+                        if app.layout.in_devices(column, row) {
+                            command = Some(Command::Action(if down {
+                                Action::MoveDown
+                            } else {
+                                Action::MoveUp
+                            }));
+                        } else if app.layout.in_logger(column, row) {
+                            app.logger_state.transition(if down {
+                                &TuiWidgetEvent::NextPageKey
+                            } else {
+                                &TuiWidgetEvent::PrevPageKey
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            AppEvent::Resize(width, height) => {
+                debug!("Terminal resized to {}x{}", width, height);
+                draw_frame(&mut terminal, &mut app, scanning(&bluetooth)).await;
+            }
+            AppEvent::Paste(text) => {
+                debug!("Received pasted text : {:?}", text);
+                // Pasted text only goes to the prompts, on a single line
+                let chars = text.chars().filter(|c| !c.is_control());
+                if let Some(input_popup) = &mut app.input_popup {
+                    chars.for_each(|c| input_popup.push(c));
+                } else if let Some(palette) = &mut app.palette {
+                    chars.for_each(|c| palette.push(c));
+                }
+            }
+            AppEvent::Focus(gained) => {
+                trace!("Terminal focus {}", if gained { "gained" } else { "lost" });
+            }
+            AppEvent::InputClosed(error) => {
+                match error {
+                    Some(err) => error!("Terminal input failed, shutting down : {}", err),
+                    None => error!("Terminal input closed, shutting down"),
+                }
+                break;
+            }
+            AppEvent::Signal(name) => {
+                info!("Shutting down on {}", name);
+                break;
+            }
+            // Stopped before drawing anything
+            AppEvent::Panicked => {}
+            AppEvent::ConfigChanged => {
+                // Nothing changes unless the whole file is valid
+                let reloaded = settings.reloader.reload().and_then(|live| {
+                    Ok((
+                        Keymap::new(live.keys)?,
+                        Theme::new(live.theme, live.no_color)?,
+                        live.show_unknown,
+                    ))
+                });
+                match reloaded {
+                    Ok((keymap, theme, show_unknown)) => {
+                        app.keymap = keymap;
+                        app.theme = theme;
+                        app.devices.show_unknown = show_unknown;
+                        debug!("Reloaded {}", settings.reloader.path().display());
+                    }
+                    Err(err) => report_error(&mut app, "Kept the previous settings", &err),
+                }
+            }
+            AppEvent::Command(event_command) => {
+                command = Some(event_command);
+            }
+            AppEvent::Operation(OperationEvent::Finished {
+                id,
+                address,
+                name,
+                operation,
+                result,
+            }) => {
+                // The device event may have finished it already, and
+                // cancelled operations are not reported
+                if !app.operations.finish_run(&address, operation, id, &result) {
+                    continue;
+                }
+                if operation == Operation::Connect {
+                    let error = result.as_ref().err().map(|err| err.to_string());
+                    report_connect_attempt(&mut app, &address, error);
+                }
+                match result {
+                    Ok(()) => {
+                        info!(
+                            address = address.as_str(), event = "operation_done";
+                            "{} {}", operation.done(), name
+                        );
+                        app.toasts.success(format!("{} {}", operation.done(), name));
+                        if operation == Operation::Pair {
+                            refresh_device(&mut app, controller(&bluetooth), &address).await;
+                        }
+                    }
+                    Err(err) => {
+                        if matches!(err, bltui::Error::Timeout(_)) && operation.may_pair() {
+                            cancel_pairing(&app, controller(&bluetooth), &address);
+                        }
+                        error!(
+                            address = address.as_str(), event = "operation_failed";
+                            "{} {} : {}", operation.failed(), name, err
+                        );
+                        app.toasts
+                            .error(format!("{} {} : {}", operation.failed(), name, err));
+                    }
                 }
-                // TODO : If popup, use popup key handler, otherwise use the main one
             }
             AppEvent::Tick => {
                 trace!("Frame tick");
-                draw_frame(&mut terminal, &mut app, bt_controller.scanning).await;
+                app.toasts.expire();
+                draw_frame(&mut terminal, &mut app, scanning(&bluetooth)).await;
+                if !app.operations.is_empty() {
+                    frames.request_in(SPINNER_RATE);
+                }
                 // if popup.is_some() {
                 // draw_popup();
                 // }
             }
         }
+
+        let Some(command) = command else {
+            continue;
+        };
+        debug!("Running command : {:?}", command);
+        match command {
+            Command::Action(Action::MoveDown) if app.logger_focused => {
+                app.logger_state.transition(&TuiWidgetEvent::DownKey);
+            }
+            Command::Action(Action::MoveUp) if app.logger_focused => {
+                app.logger_state.transition(&TuiWidgetEvent::UpKey);
+            }
+            Command::Action(Action::MoveDown) => {
+                app.devices.move_selector_down();
+            }
+            Command::Action(Action::MoveUp) => {
+                app.devices.move_selector_up();
+            }
+            Command::Action(Action::Connect) => {
+                if let Some(device) = app.devices.get_selected_device().await {
+                    app.autoconnect.resume(&device.address);
+                    start_operation(
+                        &mut app,
+                        controller(&bluetooth),
+                        &device,
+                        Operation::Connect,
+                    );
+                }
+            }
+            Command::Action(Action::Disconnect) => {
+                if let Some(device) = app.devices.get_selected_device().await {
+                    app.autoconnect.pause(&device.address);
+                    start_operation(
+                        &mut app,
+                        controller(&bluetooth),
+                        &device,
+                        Operation::Disconnect,
+                    );
+                }
+            }
+            Command::Action(Action::Pair) => {
+                if let Some(device) = app.devices.get_selected_device().await {
+                    start_operation(&mut app, controller(&bluetooth), &device, Operation::Pair);
+                }
+            }
+            Command::Action(Action::CancelOperation) => {
+                if let Some(device) = app.devices.get_selected_device().await {
+                    let cancelled = app.operations.cancel(&device.address);
+                    // Not retried until the next manual connection
+                    if cancelled.contains(&Operation::Connect) {
+                        app.autoconnect.pause(&device.address);
+                    }
+                    if cancelled.iter().any(Operation::may_pair) {
+                        cancel_pairing(&app, controller(&bluetooth), &device.address);
+                    }
+                    for operation in cancelled {
+                        app.toasts.warning(format!(
+                            "{} {}",
+                            operation.cancelled(),
+                            device.display_name()
+                        ));
+                    }
+                }
+            }
+            Command::Action(Action::Favourite) => {
+                if let Some(device) = app.devices.get_selected_device().await {
+                    match app.autoconnect.toggle_favourite(&device.address) {
+                        Ok(true) => info!("Added {} to favourites", device.name),
+                        Ok(false) => info!("Removed {} from favourites", device.name),
+                        Err(err) => report_error(&mut app, "Failed saving the favourites", &err),
+                    }
+                }
+            }
+            Command::Action(Action::Rename) => {
+                if let Some(device) = app.devices.get_selected_device().await {
+                    app.input_popup = Some(InputPopup::new(
+                        InputPurpose::Rename {
+                            periph_id: device.periph_id.clone(),
+                            address: device.address.clone(),
+                        },
+                        format!("Rename {}", device.name),
+                        device.alias.clone().unwrap_or_default(),
+                    ));
+                }
+            }
+            Command::Action(Action::Pin) => match app.devices.toggle_selected_pin() {
+                Some(true) => info!("Pinned the selected device"),
+                Some(false) => info!("Unpinned the selected device"),
+                None => {}
+            },
+            Command::Action(Action::Scan) => {
+                let Some(bluetooth) = &mut bluetooth else {
+                    not_replayable(&mut app, "Scanning");
+                    continue;
+                };
+                if let Err(err) = bluetooth.controller.trigger_scan().await {
+                    report_error(&mut app, "Failed toggling the scan", &err);
+                }
+            }
+            Command::Action(Action::Help) => {
+                app.show_help = true;
+            }
+            Command::Action(Action::Palette) => {
+                app.palette = Some(CommandPalette::new());
+            }
+            Command::Action(Action::GrowList) => {
+                app.panes.resize_list(RESIZE_STEP);
+                save_layout(&app);
+            }
+            Command::Action(Action::ShrinkList) => {
+                app.panes.resize_list(-RESIZE_STEP);
+                save_layout(&app);
+            }
+            Command::Action(Action::GrowDetails) => {
+                app.panes.resize_details(RESIZE_STEP);
+                save_layout(&app);
+            }
+            Command::Action(Action::ShrinkDetails) => {
+                app.panes.resize_details(-RESIZE_STEP);
+                save_layout(&app);
+            }
+            Command::Action(Action::ToggleLogger) => {
+                app.panes.toggle_logger();
+                save_layout(&app);
+            }
+            Command::Action(Action::Zoom) => {
+                app.panes.cycle_zoom();
+            }
+            Command::Action(Action::FocusLogger) => {
+                if app.logger_focused {
+                    leave_logger(&mut app);
+                } else {
+                    // A hidden logger is shown full screen
+                    if app.layout.logger.area() == 0 {
+                        app.panes.zoom = Some(Pane::Logger);
+                    }
+                    app.logger_focused = true;
+                }
+            }
+            Command::Action(Action::LogPageUp) => {
+                app.logger_state.transition(&TuiWidgetEvent::PrevPageKey);
+            }
+            Command::Action(Action::LogPageDown) => {
+                app.logger_state.transition(&TuiWidgetEvent::NextPageKey);
+            }
+            Command::Action(Action::LogFollow) => {
+                app.logger_state.transition(&TuiWidgetEvent::EscapeKey);
+            }
+            Command::Action(action @ (Action::LogLevelUp | Action::LogLevelDown)) => {
+                let level = next_level(log::max_level(), action == Action::LogLevelUp);
+                set_level(level, &mut app.logger_state);
+                app.toasts.success(format!("Log level set to {}", level));
+            }
+            Command::Action(Action::LogShowMore) => {
+                app.logger_state.transition(&TuiWidgetEvent::RightKey);
+            }
+            Command::Action(Action::LogShowLess) => {
+                app.logger_state.transition(&TuiWidgetEvent::LeftKey);
+            }
+            Command::Action(Action::LogHideOff) => {
+                app.logger_state.transition(&TuiWidgetEvent::SpaceKey);
+            }
+            Command::Action(Action::LogTargets) => {
+                app.logger_state.transition(&TuiWidgetEvent::HideKey);
+            }
+            Command::Action(Action::LogFullscreen) => {
+                app.panes.zoom = match app.panes.zoom {
+                    Some(Pane::Logger) => None,
+                    _ => Some(Pane::Logger),
+                };
+            }
+            Command::Action(Action::Quit) => {
+                break;
+            }
+            Command::Action(Action::Cancel) => {
+                leave_logger(&mut app);
+            }
+            Command::Action(Action::Confirm) => {}
+            Command::ScanFor(duration) => {
+                let Some(bluetooth) = &mut bluetooth else {
+                    not_replayable(&mut app, "Scanning");
+                    continue;
+                };
+                if !bluetooth.controller.scanning {
+                    if let Err(err) = bluetooth.controller.trigger_scan().await {
+                        report_error(&mut app, "Failed starting the scan", &err);
+                        continue;
+                    }
+                }
+                info!("Scanning for {:?}", duration);
+                let tx = app.tx();
+                tokio::spawn(async move {
+                    tokio::time::sleep(duration).await;
+                    let _ = tx.send(AppEvent::Command(Command::StopScan)).await;
+                });
+            }
+            Command::StopScan => {
+                let Some(bluetooth) = &mut bluetooth else {
+                    continue;
+                };
+                if bluetooth.controller.scanning {
+                    if let Err(err) = bluetooth.controller.trigger_scan().await {
+                        report_error(&mut app, "Failed stopping the scan", &err);
+                    }
+                }
+            }
+            Command::Adapter(name) => {
+                let Some(bluetooth) = &mut bluetooth else {
+                    not_replayable(&mut app, "Switching adapters");
+                    continue;
+                };
+                let connection = bluetooth.agent.connection();
+                match switch_adapter(&mut bluetooth.controller, &name, connection).await {
+                    Ok(events) => {
+                        bluetooth.adapter_watcher.abort();
+                        bluetooth.adapter_watcher = spawn_adapter_watcher(events, app.tx()).await;
+                        app.devices.clear();
+                        match bluetooth.controller.get_paired_devices().await {
+                            Ok(devices) => {
+                                for device in devices {
+                                    app.insert_device(app.aliases.apply(device));
+                                }
+                            }
+                            Err(err) => {
+                                report_error(&mut app, "Failed listing paired devices", &err)
+                            }
+                        }
+                        info!("Switched to adapter {}", name);
+                    }
+                    Err(err) => report_error(
+                        &mut app,
+                        &format!("Failed switching to adapter {}", name),
+                        &err,
+                    ),
+                }
+            }
+            Command::Sort(order) => {
+                app.devices.sort = order;
+                info!("Sorting devices by {}", order.name());
+            }
+        }
+    }
+
+    if let Some(bluetooth) = &bluetooth {
+        if let Err(err) = bluetooth.agent.unregister().await {
+            error!("Failed unregistering the agent : {}", err);
+        }
     }
 
-    agent.unregister().await;
+    save_cache(&app);
 
-    disable_raw_mode()?;
+    terminal_guard.restore()?;
 
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    if panicked {
+        std::process::exit(101);
+    }
 
     Ok(())
 }
@@ -1,136 +1,630 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use dirs::home_dir;
-use log::{info, LevelFilter};
-use serde::Deserialize;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use toml_edit::{Document, Item, Table};
 
-use crate::Error;
+use crate::{
+    hooks::Hook,
+    keymap::{Action, Keys},
+    logging::{LogFormat, LogLevel, LoggingConfig},
+    ui::{layout::LayoutConfig, theme::ThemeConfig},
+    Error,
+};
 
-#[derive(Deserialize, Default)]
+/// Commented config file written by `bltui config init`
+static DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+/// Settings of `config.toml`. Every setting can also be given on the
+/// command line, which takes precedence
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 struct Config {
     adapter: Option<String>,
+    log_level: Option<LogLevel>,
+    log_to_file: Option<bool>,
     log_path: Option<PathBuf>,
+    log_format: Option<LogFormat>,
+    show_unknown: Option<bool>,
+    mouse: Option<bool>,
+    no_color: Option<bool>,
+    max_fps: Option<u32>,
+    #[serde(default)]
+    favourites: Vec<String>,
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    keys: BTreeMap<String, Keys>,
+    #[serde(default)]
+    theme: ThemeConfig,
+    #[serde(default)]
+    layout: LayoutConfig,
+    #[serde(default)]
+    logging: LoggingConfig,
+    #[serde(default)]
+    hooks: Vec<Hook>,
+}
+
+/// Error of the config file, without the position toml appends to the
+/// message
+fn config_error(config_path: &Path, err: toml::de::Error) -> Error {
+    let message = err.to_string();
+    let position = err.line_col().map(|(line, column)| (line + 1, column + 1));
+    let message = match position {
+        Some(_) => message
+            .rsplit_once(" at line ")
+            .map_or(message.as_str(), |(message, _)| message)
+            .to_string(),
+        None => message,
+    };
+
+    Error::InvalidConfig {
+        path: config_path.to_path_buf(),
+        position,
+        message,
+    }
+}
+
+/// Set `value` at the dotted `key` of `table`, creating the tables
+/// on the way
+fn set_value(table: &mut toml::value::Table, key: &str, value: toml::Value) -> Result<(), Error> {
+    let mut parts: Vec<&str> = key.split('.').map(str::trim).collect();
+    let last = parts.pop().unwrap_or_default();
+    if last.is_empty() || parts.iter().any(|part| part.is_empty()) {
+        return Err(Error::InvalidInput(format!("`{}` is not a setting", key)));
+    }
+
+    let mut table = table;
+    for part in parts {
+        table = table
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::value::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| Error::InvalidInput(format!("`{}` is not a table", part)))?;
+    }
+    table.insert(last.to_string(), value);
+
+    Ok(())
 }
 
 impl Config {
     fn read_from(config_path: &Path) -> Result<Config, Error> {
         let content = std::fs::read_to_string(config_path)?;
 
-        match toml::from_str(&content) {
-            Ok(conf) => Ok(conf),
-            Err(_) => Err(Error::InvalidConfigFile(config_path.to_path_buf())),
+        toml::from_str(&content).map_err(|err| config_error(config_path, err))
+    }
+
+    /// The config file, or nothing if there is none, with `overrides`
+    /// set over it
+    fn load(config_path: &Path, overrides: Vec<(String, toml::Value)>) -> Result<Config, Error> {
+        let mut config = if config_path.exists() {
+            Config::read_from(config_path)?
+        } else {
+            Config::default()
+        };
+        if overrides.is_empty() {
+            return Ok(config);
         }
+
+        let mut table = match toml::Value::try_from(&config) {
+            Ok(toml::Value::Table(table)) => table,
+            Ok(_) => toml::value::Table::new(),
+            Err(err) => return Err(Error::InvalidInput(err.to_string())),
+        };
+        // Checked one by one to tell which setting is wrong
+        for (key, value) in overrides {
+            set_value(&mut table, &key, value)?;
+            config = toml::Value::Table(table.clone())
+                .try_into()
+                .map_err(|err| {
+                    Error::InvalidInput(format!("{} in the command line setting `{}`", err, key))
+                })?;
+        }
+
+        Ok(config)
     }
+
+    /// The config as TOML. Going through a value writes the tables
+    /// after the other settings, as TOML requires
+    fn dump(&self) -> Result<String, Error> {
+        toml::Value::try_from(self)
+            .and_then(|value| toml::to_string(&value))
+            .map_err(|err| Error::InvalidInput(err.to_string()))
+    }
+
+    /// Every setting, with the defaults of the unset ones
+    fn with_defaults(self, log_folder: PathBuf) -> Config {
+        let mut keys = self.keys;
+        for action in Action::ALL {
+            keys.entry(action.name().to_string())
+                .or_insert_with(|| Keys::default_of(action));
+        }
+
+        Config {
+            adapter: self.adapter,
+            log_level: self.log_level.or(Some(LogLevel::default())),
+            log_to_file: self.log_to_file.or(Some(false)),
+            log_path: self.log_path.or(Some(log_folder)),
+            log_format: self.log_format.or(Some(LogFormat::default())),
+            show_unknown: self.show_unknown.or(Some(false)),
+            mouse: self.mouse.or(Some(false)),
+            no_color: self.no_color.or(Some(false)),
+            max_fps: self.max_fps.or(Some(DEFAULT_MAX_FPS)),
+            favourites: self.favourites,
+            aliases: self.aliases,
+            keys,
+            theme: ThemeConfig {
+                preset: self.theme.preset.or(Some("dark".to_string())),
+                colors: self.theme.colors,
+            },
+            layout: self.layout.with_defaults(),
+            logging: self.logging.with_defaults(),
+            hooks: self.hooks,
+        }
+    }
+}
+
+/// Edit the config file in place, keeping the user's comments and
+/// formatting. `edit` returns `None` when the file doesn't have the
+/// expected structure
+pub fn edit_config_file(
+    config_file: &Path,
+    edit: impl FnOnce(&mut Document) -> Option<()>,
+) -> Result<(), Error> {
+    let content = match std::fs::read_to_string(config_file) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let mut document = content
+        .parse::<Document>()
+        .map_err(|_| Error::InvalidConfigFile(config_file.to_path_buf()))?;
+
+    edit(&mut document).ok_or_else(|| Error::InvalidConfigFile(config_file.to_path_buf()))?;
+
+    if let Some(folder) = config_file.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    std::fs::write(config_file, document.to_string())?;
+
+    Ok(())
+}
+
+/// Table `name` of the config file, created if missing
+pub fn config_table<'a>(document: &'a mut Document, name: &str) -> Option<&'a mut Table> {
+    document
+        .entry(name)
+        .or_insert(Item::Table(Table::new()))
+        .as_table_mut()
+}
+
+/// Setting of the command line, as `key=value`. Values are read as
+/// TOML, and as plain strings when they aren't valid TOML
+fn parse_setting(setting: &str) -> Result<(String, toml::Value), String> {
+    let (key, value) = setting
+        .split_once('=')
+        .ok_or_else(|| format!("`{}` is not key=value", setting))?;
+    let value = toml::from_str::<toml::value::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+
+    Ok((key.trim().to_string(), value))
 }
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct CliSettings {
-    /// Activate debug/trace messages
-    #[arg(short, long, action = clap::ArgAction::Count)]
+    /// Start with debug/trace messages, the level can be changed with `[` and `]`
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "log_level")]
     debug: u8,
 
+    /// Lowest level of the messages shown and written
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<LogLevel>,
+
     /// Display devices with an unknown name
     #[arg(short = 'u', long, action)]
     show_unknown: bool,
 
-    /// Log to file (/$homedir/.bltui/logs)
+    /// Log to files, in the logs of the state folder
     #[arg(short, long, action)]
     log_to_file: bool,
 
+    /// Folder of the log files
+    #[arg(long, value_name = "FOLDER")]
+    log_path: Option<PathBuf>,
+
+    /// Format of the log files
+    #[arg(long, value_name = "FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Config file to use instead of config.toml in the config folder
+    #[arg(short, long = "config", value_name = "FILE")]
+    config_file: Option<PathBuf>,
+
     /// Specify which adapter to use
     #[arg(short, long)]
     adapter: Option<String>,
+
+    /// Capture the mouse to select devices and scroll
+    #[arg(short, long, action)]
+    mouse: bool,
+
+    /// Draw without colours, like with the NO_COLOR variable
+    #[arg(long, action)]
+    no_color: bool,
+
+    /// Maximum number of frames drawn per second
+    #[arg(long)]
+    max_fps: Option<u32>,
+
+    /// Set any setting of config.toml, like `layout.preset=columns`
+    #[arg(short = 'o', long = "set", value_name = "KEY=VALUE", value_parser = parse_setting)]
+    settings: Vec<(String, toml::Value)>,
+
+    /// Record the session to a file, to replay it later
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Replay a recorded session, without using Bluetooth
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// How many times faster than recorded the session is replayed
+    #[arg(long, value_name = "FACTOR", default_value_t = 1.0, value_parser = parse_speed)]
+    replay_speed: f64,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(Debug, Subcommand)]
+enum CliCommand {
+    /// Manage the config file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Print the settings in effect, from the config file and the command line
+    Dump,
+    /// Write a config file documenting every setting
+    Init {
+        /// Replace the existing config file
+        #[arg(long, action)]
+        force: bool,
+    },
+}
+
+fn parse_speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("{} is not a positive number", value)),
+    }
+}
+
+/// A setting given on the command line
+fn setting(key: &str, value: impl Serialize) -> (String, toml::Value) {
+    (
+        key.to_string(),
+        toml::Value::try_from(value).expect("settings are valid TOML"),
+    )
 }
 
 impl CliSettings {
-    fn get_log_level(&self) -> LevelFilter {
+    fn get_log_level(&self) -> Option<LogLevel> {
         match self.debug {
-            0 => LevelFilter::Info,
-            1 => LevelFilter::Debug,
-            2 => LevelFilter::Trace,
-            _ => LevelFilter::Trace,
+            0 => self.log_level,
+            1 => Some(LogLevel::Debug),
+            _ => Some(LogLevel::Trace),
+        }
+    }
+
+    /// Settings of the command line to set over the config file, the
+    /// flags after the `--set` settings
+    fn overrides(&self) -> Vec<(String, toml::Value)> {
+        let mut overrides = self.settings.clone();
+        if let Some(adapter) = &self.adapter {
+            overrides.push(setting("adapter", adapter));
+        }
+        if let Some(level) = self.get_log_level() {
+            overrides.push(setting("log_level", level));
         }
+        if self.log_to_file {
+            overrides.push(setting("log_to_file", true));
+        }
+        if let Some(log_path) = &self.log_path {
+            overrides.push(setting("log_path", log_path.to_string_lossy()));
+        }
+        if let Some(format) = self.log_format {
+            overrides.push(setting("log_format", format));
+        }
+        if self.show_unknown {
+            overrides.push(setting("show_unknown", true));
+        }
+        if self.mouse {
+            overrides.push(setting("mouse", true));
+        }
+        if self.no_color {
+            overrides.push(setting("no_color", true));
+        }
+        if let Some(max_fps) = self.max_fps {
+            overrides.push(setting("max_fps", max_fps));
+        }
+        overrides
     }
 }
 
-fn get_bltui_folder() -> PathBuf {
-    match std::env::var("BLTUI_FOLDER") {
-        Ok(path_str) => {
-            println!("Using BLTUI_FOLDER environment variable");
-            PathBuf::from(path_str)
+static DEFAULT_MAX_FPS: u32 = 30;
+
+/// Folder of bltui in each XDG base directory
+static APP_FOLDER: &str = "bltui";
+
+/// Where the files of bltui are kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Folders {
+    /// config.toml
+    config: PathBuf,
+    /// Pins and logs
+    state: PathBuf,
+    /// Devices seen in previous sessions
+    cache: PathBuf,
+}
+
+impl Folders {
+    /// `BLTUI_FOLDER` for every file when it is set, the XDG base
+    /// directories otherwise
+    fn from_env(var: impl Fn(&str) -> Option<OsString>, home: &Path) -> Folders {
+        if let Some(folder) = var("BLTUI_FOLDER") {
+            let folder = PathBuf::from(folder);
+            return Folders {
+                config: folder.clone(),
+                state: folder.clone(),
+                cache: folder,
+            };
+        }
+
+        // Relative paths are invalid and ignored, as the specification asks
+        let xdg_folder = |name: &str, default: &str| {
+            var(name)
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .unwrap_or_else(|| home.join(default))
+                .join(APP_FOLDER)
+        };
+        Folders {
+            config: xdg_folder("XDG_CONFIG_HOME", ".config"),
+            state: xdg_folder("XDG_STATE_HOME", ".local/state"),
+            cache: xdg_folder("XDG_CACHE_HOME", ".cache"),
+        }
+    }
+}
+
+/// Move the files of `~/.bltui`, where they all used to be, to the
+/// XDG folders. Files already at their new place are left alone
+fn migrate_legacy_folder(legacy: &Path, folders: &Folders) {
+    if !legacy.is_dir() {
+        return;
+    }
+
+    for (name, folder) in [
+        ("config.toml", &folders.config),
+        ("pins.toml", &folders.state),
+        ("logs", &folders.state),
+        ("devices.toml", &folders.cache),
+    ] {
+        let from = legacy.join(name);
+        let to = folder.join(name);
+        if !from.exists() || to.exists() {
+            continue;
         }
-        Err(_) => {
-            let mut path = home_dir().expect("Could not get home directory");
-            path.push(".bltui");
-            path
+        match std::fs::create_dir_all(folder).and_then(|_| std::fs::rename(&from, &to)) {
+            Ok(()) => eprintln!("Moved {} to {}", from.display(), to.display()),
+            Err(err) => eprintln!(
+                "Failed moving {} to {} : {}",
+                from.display(),
+                to.display(),
+                err
+            ),
         }
     }
+
+    // Only removed once everything was moved
+    let _ = std::fs::remove_dir(legacy);
+}
+
+/// Folders of the files, where those of `~/.bltui` are moved when
+/// `migrate` is set. `config` commands leave them where they are
+fn get_folders(migrate: bool) -> Folders {
+    let home = home_dir().expect("Could not get home directory");
+    let folders = Folders::from_env(
+        |name| std::env::var_os(name).filter(|value| !value.is_empty()),
+        &home,
+    );
+
+    if std::env::var_os("BLTUI_FOLDER").is_some_and(|value| !value.is_empty()) {
+        eprintln!("Using BLTUI_FOLDER environment variable");
+    } else if migrate {
+        migrate_legacy_folder(&home.join(".bltui"), &folders);
+    }
+
+    folders
+}
+
+/// Write the commented default config, keeping an existing one unless
+/// `force` is set
+fn init_config_file(config_file: &Path, force: bool) -> Result<(), Error> {
+    if config_file.exists() && !force {
+        return Err(Error::InvalidInput(format!(
+            "{} already exists, use --force to replace it",
+            config_file.display()
+        )));
+    }
+    if let Some(folder) = config_file.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    std::fs::write(config_file, DEFAULT_CONFIG)?;
+
+    Ok(())
 }
 
 pub struct LogSettings {
     pub level: LevelFilter,
     pub log_to_file: bool,
     pub folder: PathBuf,
+    pub format: LogFormat,
+    /// Rotation and retention of the log files
+    pub files: LoggingConfig,
+}
+
+/// Settings applied again when the config file changes
+pub struct LiveSettings {
+    pub keys: HashMap<String, Keys>,
+    pub theme: ThemeConfig,
+    pub no_color: bool,
+    pub show_unknown: bool,
+}
+
+impl LiveSettings {
+    fn take(config: &mut Config) -> LiveSettings {
+        LiveSettings {
+            keys: std::mem::take(&mut config.keys).into_iter().collect(),
+            theme: std::mem::take(&mut config.theme),
+            no_color: config.no_color.unwrap_or(false)
+                || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()),
+            show_unknown: config.show_unknown.unwrap_or(false),
+        }
+    }
+}
+
+/// Reads the config file again, with the command line settings over it
+pub struct ConfigReloader {
+    path: PathBuf,
+    overrides: Vec<(String, toml::Value)>,
+}
+
+impl ConfigReloader {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn reload(&self) -> Result<LiveSettings, Error> {
+        let mut config = Config::load(&self.path, self.overrides.clone())?;
+        Ok(LiveSettings::take(&mut config))
+    }
 }
 
 pub struct AppSettings {
     pub log_settings: LogSettings,
     pub adapter: Option<String>,
-    pub show_unknown: bool,
+    pub live: LiveSettings,
+    pub reloader: ConfigReloader,
+    pub mouse: bool,
+    pub max_fps: u32,
+    pub favourites: Vec<String>,
+    pub config_file: PathBuf,
+    pub pins_file: PathBuf,
+    pub cache_file: PathBuf,
+    pub aliases: HashMap<String, String>,
+    pub layout: LayoutConfig,
+    pub hooks: Vec<Hook>,
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub replay_speed: f64,
 }
 
 impl AppSettings {
-    fn from_cli_and_file_settings(cli_settings: CliSettings, file_config: Config) -> AppSettings {
+    fn from_cli_and_config(
+        cli_settings: CliSettings,
+        mut config: Config,
+        folders: &Folders,
+        config_file: PathBuf,
+    ) -> AppSettings {
+        let overrides = cli_settings.overrides();
         AppSettings {
-            log_settings: LogSettings {
-                level: cli_settings.get_log_level(),
-                log_to_file: cli_settings.log_to_file,
-                folder: if file_config.log_path.is_some() {
-                    file_config.log_path.unwrap()
-                } else {
-                    let mut log_folder = get_bltui_folder();
-                    log_folder.push("logs");
-                    log_folder
-                },
+            live: LiveSettings::take(&mut config),
+            reloader: ConfigReloader {
+                path: config_file.clone(),
+                overrides,
             },
-            adapter: {
-                if cli_settings.adapter.is_some() {
-                    cli_settings.adapter
-                } else {
-                    file_config.adapter
-                }
+            log_settings: LogSettings {
+                level: config.log_level.unwrap_or_default().into(),
+                log_to_file: config.log_to_file.unwrap_or(false),
+                folder: config
+                    .log_path
+                    .unwrap_or_else(|| folders.state.join("logs")),
+                format: config.log_format.unwrap_or_default(),
+                files: config.logging,
             },
-            show_unknown: cli_settings.show_unknown,
+            adapter: config.adapter,
+            mouse: config.mouse.unwrap_or(false),
+            max_fps: config.max_fps.unwrap_or(DEFAULT_MAX_FPS).max(1),
+            favourites: config.favourites,
+            config_file,
+            pins_file: folders.state.join("pins.toml"),
+            cache_file: folders.cache.join("devices.toml"),
+            aliases: config.aliases.into_iter().collect(),
+            layout: config.layout,
+            hooks: config.hooks,
+            record: cli_settings.record,
+            replay: cli_settings.replay,
+            replay_speed: cli_settings.replay_speed,
         }
     }
 
-    pub fn parse() -> AppSettings {
-        let mut config_path = get_bltui_folder();
-        config_path.push("config.toml");
-
-        let file_config = match Config::read_from(&config_path) {
-            Ok(conf) => conf,
-            Err(err) => match err {
-                Error::InvalidConfigFile(path) => {
-                    panic!("Invalid config file at {:?}", path);
-                }
-                Error::IOError(_) => Config::default(),
-                _ => {
-                    panic!("Unexpected error");
-                }
-            },
-        };
+    /// Settings of the command line over the config file. `None` when
+    /// a `config` command was run instead of the app
+    pub fn parse() -> Result<Option<AppSettings>, Error> {
         let cli_settings = CliSettings::parse();
+        let folders = get_folders(cli_settings.command.is_none());
+        let config_path = cli_settings
+            .config_file
+            .clone()
+            .unwrap_or_else(|| folders.config.join("config.toml"));
+
+        if let Some(CliCommand::Config {
+            command: ConfigCommand::Init { force },
+        }) = cli_settings.command
+        {
+            init_config_file(&config_path, force)?;
+            println!("Wrote {}", config_path.display());
+            return Ok(None);
+        }
+
+        let config = Config::load(&config_path, cli_settings.overrides())?;
+
+        if let Some(CliCommand::Config {
+            command: ConfigCommand::Dump,
+        }) = cli_settings.command
+        {
+            let config = config.with_defaults(folders.state.join("logs"));
+            print!("{}", config.dump()?);
+            return Ok(None);
+        }
 
-        AppSettings::from_cli_and_file_settings(cli_settings, file_config)
+        Ok(Some(AppSettings::from_cli_and_config(
+            cli_settings,
+            config,
+            &folders,
+            config_path,
+        )))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{hooks::HookEvent, keymap::Keymap, ui::layout::LayoutPreset};
     use rand::Rng;
 
     struct TempDir {
@@ -141,7 +635,7 @@ mod tests {
         fn new() -> TempDir {
             let mut temp_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
             temp_dir.push("temp");
-            temp_dir.push(format!("{}", rand::thread_rng().gen_range(0..1000)));
+            temp_dir.push(format!("{}", rand::thread_rng().gen_range(0..100000)));
             std::fs::create_dir_all(&temp_dir).unwrap();
             TempDir { path: temp_dir }
         }
@@ -157,21 +651,134 @@ mod tests {
         }
     }
 
+    fn folders_with(vars: &[(&str, &str)]) -> Folders {
+        Folders::from_env(
+            |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| OsString::from(value))
+            },
+            Path::new("/home/simon"),
+        )
+    }
+
+    #[test]
+    fn test_folders_bltui_folder() {
+        let folders = folders_with(&[("BLTUI_FOLDER", "/bltui"), ("XDG_CONFIG_HOME", "/xdg")]);
+
+        assert_eq!(folders.config, PathBuf::from("/bltui"));
+        assert_eq!(folders.state, PathBuf::from("/bltui"));
+        assert_eq!(folders.cache, PathBuf::from("/bltui"));
+    }
+
+    #[test]
+    fn test_folders_xdg() {
+        let folders = folders_with(&[]);
+        assert_eq!(folders.config, PathBuf::from("/home/simon/.config/bltui"));
+        assert_eq!(
+            folders.state,
+            PathBuf::from("/home/simon/.local/state/bltui")
+        );
+        assert_eq!(folders.cache, PathBuf::from("/home/simon/.cache/bltui"));
+
+        let folders = folders_with(&[
+            ("XDG_CONFIG_HOME", "/etc/xdg"),
+            ("XDG_STATE_HOME", "relative/state"),
+            ("XDG_CACHE_HOME", "/var/cache"),
+        ]);
+        assert_eq!(folders.config, PathBuf::from("/etc/xdg/bltui"));
+        assert_eq!(
+            folders.state,
+            PathBuf::from("/home/simon/.local/state/bltui")
+        );
+        assert_eq!(folders.cache, PathBuf::from("/var/cache/bltui"));
+    }
+
+    #[test]
+    fn test_migrate_legacy_folder() {
+        let temp_dir = TempDir::new();
+        let legacy = temp_dir.path().join(".bltui");
+        std::fs::create_dir_all(legacy.join("logs")).unwrap();
+        std::fs::write(legacy.join("config.toml"), "mouse = true").unwrap();
+        std::fs::write(legacy.join("pins.toml"), "").unwrap();
+        std::fs::write(legacy.join("logs/latest.log"), "").unwrap();
+        std::fs::write(legacy.join("devices.toml"), "").unwrap();
+        let folders = Folders {
+            config: temp_dir.path().join("config/bltui"),
+            state: temp_dir.path().join("state/bltui"),
+            cache: temp_dir.path().join("cache/bltui"),
+        };
+
+        migrate_legacy_folder(&legacy, &folders);
+
+        assert!(!legacy.exists());
+        assert_eq!(
+            std::fs::read_to_string(folders.config.join("config.toml")).unwrap(),
+            "mouse = true"
+        );
+        assert!(folders.state.join("pins.toml").exists());
+        assert!(folders.state.join("logs/latest.log").exists());
+        assert!(folders.cache.join("devices.toml").exists());
+    }
+
     #[test]
-    fn test_get_bltui_folder_env() {
-        let folder = PathBuf::from("/home/simon");
-        std::env::set_var("BLTUI_FOLDER", folder.to_str().unwrap());
-        let returned_folder = get_bltui_folder();
-        std::env::remove_var("BLTUI_FOLDER");
-        assert_eq!(folder, returned_folder);
+    fn test_migration_keeps_newer_files() {
+        let temp_dir = TempDir::new();
+        let legacy = temp_dir.path().join(".bltui");
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::write(legacy.join("config.toml"), "mouse = true").unwrap();
+        let folders = Folders {
+            config: temp_dir.path().join("config"),
+            state: temp_dir.path().join("state"),
+            cache: temp_dir.path().join("cache"),
+        };
+        std::fs::create_dir_all(&folders.config).unwrap();
+        std::fs::write(folders.config.join("config.toml"), "mouse = false").unwrap();
+
+        migrate_legacy_folder(&legacy, &folders);
+
+        assert!(legacy.join("config.toml").exists());
+        assert_eq!(
+            std::fs::read_to_string(folders.config.join("config.toml")).unwrap(),
+            "mouse = false"
+        );
     }
 
     #[test]
-    fn test_get_bltui_folder_home() {
-        let returned_folder = get_bltui_folder();
-        let mut folder = home_dir().unwrap();
-        folder.push(".bltui");
-        assert_eq!(folder, returned_folder);
+    fn test_reload_keeps_cli_settings() {
+        let temp_dir = TempDir::new();
+        let config_path = temp_dir.path().join("custom.toml");
+        std::fs::write(&config_path, "show_unknown = true").unwrap();
+        let cli = CliSettings::try_parse_from([
+            "bltui",
+            "--config",
+            config_path.to_str().unwrap(),
+            "--set",
+            "theme.preset=light",
+        ])
+        .unwrap();
+        assert_eq!(cli.config_file.as_deref(), Some(config_path.as_path()));
+        let reloader = ConfigReloader {
+            path: config_path.clone(),
+            overrides: cli.overrides(),
+        };
+
+        std::fs::write(
+            &config_path,
+            "[theme]\npreset = \"dark\"\n[keys]\nquit = \"Q\"",
+        )
+        .unwrap();
+        let live = reloader.reload().unwrap();
+
+        assert!(!live.show_unknown);
+        assert_eq!(live.theme.preset.as_deref(), Some("light"));
+        assert!(matches!(live.keys.get("quit"), Some(Keys::One(key)) if key == "Q"));
+
+        std::fs::write(&config_path, "show_unknown = 1").unwrap();
+        assert!(matches!(
+            reloader.reload(),
+            Err(Error::InvalidConfig { .. })
+        ));
     }
 
     #[test]
@@ -228,6 +835,168 @@ mod tests {
 
         assert!(config.adapter.is_none());
         assert!(config.log_path.is_none());
+        assert!(config.mouse.is_none());
+        assert!(config.max_fps.is_none());
+    }
+
+    #[test]
+    fn test_config_parsing_mouse() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        std::fs::write(temp_dir_path.join("config.toml"), "mouse = true").unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path()).unwrap();
+
+        assert_eq!(config.mouse, Some(true));
+    }
+
+    #[test]
+    fn test_config_parsing_max_fps() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        std::fs::write(temp_dir_path.join("config.toml"), "max_fps = 10").unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path()).unwrap();
+
+        assert_eq!(config.max_fps, Some(10));
+    }
+
+    #[test]
+    fn test_config_parsing_favourites() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        let config = "favourites = [\"AA:BB:CC:DD:EE:FF\", \"11:22:33:44:55:66\"]";
+        std::fs::write(temp_dir_path.join("config.toml"), config).unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path()).unwrap();
+
+        assert_eq!(
+            config.favourites,
+            vec!["AA:BB:CC:DD:EE:FF", "11:22:33:44:55:66"]
+        );
+    }
+
+    #[test]
+    fn test_config_parsing_aliases() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        let config = "[aliases]\n\"AA:BB:CC:DD:EE:FF\" = \"Living room speaker\"";
+        std::fs::write(temp_dir_path.join("config.toml"), config).unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path()).unwrap();
+
+        assert_eq!(
+            config.aliases.get("AA:BB:CC:DD:EE:FF").unwrap(),
+            "Living room speaker"
+        );
+    }
+
+    #[test]
+    fn test_config_parsing_keys() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        let config = "[keys]\nquit = [\"q\", \"ctrl-c\"]\nconnect = \"enter\"";
+        std::fs::write(temp_dir_path.join("config.toml"), config).unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path()).unwrap();
+
+        assert!(matches!(config.keys.get("quit"), Some(Keys::Many(keys)) if keys.len() == 2));
+        assert!(matches!(config.keys.get("connect"), Some(Keys::One(key)) if key == "enter"));
+    }
+
+    #[test]
+    fn test_config_parsing_theme() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        let config = "[theme]\npreset = \"light\"\n\n[theme.colors]\nborder = \"#005f87\"";
+        std::fs::write(temp_dir_path.join("config.toml"), config).unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path()).unwrap();
+
+        assert_eq!(config.theme.preset.as_deref(), Some("light"));
+        assert_eq!(config.theme.colors.get("border").unwrap(), "#005f87");
+    }
+
+    #[test]
+    fn test_config_parsing_layout() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        let config = "[layout]\npreset = \"columns\"\nlist_size = 30\nshow_logger = false";
+        std::fs::write(temp_dir_path.join("config.toml"), config).unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path()).unwrap();
+
+        assert_eq!(config.layout.preset, Some(LayoutPreset::Columns));
+        assert_eq!(config.layout.list_size, Some(30));
+        assert!(config.layout.details_size.is_none());
+        assert_eq!(config.layout.show_logger, Some(false));
+    }
+
+    #[test]
+    fn test_config_parsing_logging() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        let config = "log_format = \"json\"\n[logging]\nmax_files = 3\nlatest_link = false";
+        std::fs::write(temp_dir_path.join("config.toml"), config).unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path()).unwrap();
+
+        assert_eq!(config.log_format, Some(LogFormat::Json));
+        assert_eq!(config.logging.max_files, Some(3));
+        assert_eq!(config.logging.latest_link, Some(false));
+        assert!(config.logging.max_file_size_mb.is_none());
+    }
+
+    #[test]
+    fn test_config_parsing_invalid_layout_preset() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        let config = "[layout]\npreset = \"diagonal\"";
+        std::fs::write(temp_dir_path.join("config.toml"), config).unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path());
+
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_config_parsing_hooks() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        let config = r#"
+[[hooks]]
+event = "connected"
+name = "*Bose*"
+command = "pactl set-default-sink bluez_sink"
+
+[[hooks]]
+event = "disconnected"
+address = "AA:BB:CC:DD:EE:FF"
+command = "loginctl lock-session"
+timeout = 3
+"#;
+        std::fs::write(temp_dir_path.join("config.toml"), config).unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path()).unwrap();
+
+        assert_eq!(config.hooks.len(), 2);
+        assert_eq!(config.hooks[0].event, HookEvent::Connected);
+        assert_eq!(config.hooks[0].name.as_deref(), Some("*Bose*"));
+        assert!(config.hooks[0].address.is_none());
+        assert_eq!(config.hooks[1].event, HookEvent::Disconnected);
+        assert_eq!(config.hooks[1].timeout, 3);
+    }
+
+    #[test]
+    fn test_config_parsing_invalid_hook_event() {
+        let temp_dir = TempDir::new();
+        let temp_dir_path = temp_dir.path();
+        let config = "[[hooks]]\nevent = \"paired\"\ncommand = \"true\"";
+        std::fs::write(temp_dir_path.join("config.toml"), config).unwrap();
+
+        let config = Config::read_from(temp_dir_path.join("config.toml").as_path());
+
+        assert!(config.is_err());
     }
 
     #[test]
@@ -264,4 +1033,167 @@ mod tests {
 
         assert!(config.is_err());
     }
+
+    #[test]
+    fn test_cli_replay() {
+        let cli = CliSettings::try_parse_from(["bltui", "--replay", "session.jsonl"]).unwrap();
+        assert_eq!(cli.replay, Some(PathBuf::from("session.jsonl")));
+        assert_eq!(cli.replay_speed, 1.0);
+
+        let cli = CliSettings::try_parse_from(["bltui", "--replay", "a", "--replay-speed", "2.5"])
+            .unwrap();
+        assert_eq!(cli.replay_speed, 2.5);
+
+        assert!(CliSettings::try_parse_from(["bltui", "--replay-speed", "0"]).is_err());
+        assert!(CliSettings::try_parse_from(["bltui", "--record", "a", "--replay", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_config_error_position() {
+        let temp_dir = TempDir::new();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(
+            &config_path,
+            "mouse = true\n\n[layout]\nlist_size = \"big\"",
+        )
+        .unwrap();
+
+        let err = Config::read_from(&config_path).unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::InvalidConfig {
+                position: Some((4, 13)),
+                ..
+            }
+        ));
+        let message = err.to_string();
+        assert!(message.contains("config.toml:4:13 : "), "{}", message);
+        assert!(
+            message.contains("for key `layout.list_size`"),
+            "{}",
+            message
+        );
+        assert!(!message.contains(" at line "), "{}", message);
+    }
+
+    #[test]
+    fn test_config_unknown_field() {
+        let temp_dir = TempDir::new();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(&config_path, "[logging]\nmax_filez = 3").unwrap();
+
+        let err = Config::read_from(&config_path).unwrap_err();
+
+        assert!(
+            err.to_string().contains("unknown field `max_filez`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_cli_settings_override_the_file() {
+        let temp_dir = TempDir::new();
+        let config_path = temp_dir.path().join("config.toml");
+        std::fs::write(
+            &config_path,
+            "max_fps = 10\nmouse = false\n[layout]\nlist_size = 30",
+        )
+        .unwrap();
+        let cli = CliSettings::try_parse_from([
+            "bltui",
+            "--set",
+            "layout.preset=columns",
+            "--set",
+            "max_fps=20",
+            "--max-fps",
+            "15",
+            "-m",
+            "-o",
+            "theme.colors.border=#005f87",
+        ])
+        .unwrap();
+
+        let config = Config::load(&config_path, cli.overrides()).unwrap();
+
+        assert_eq!(config.max_fps, Some(15));
+        assert_eq!(config.mouse, Some(true));
+        assert_eq!(config.layout.preset, Some(LayoutPreset::Columns));
+        assert_eq!(config.layout.list_size, Some(30));
+        assert_eq!(config.theme.colors.get("border").unwrap(), "#005f87");
+    }
+
+    #[test]
+    fn test_cli_invalid_setting() {
+        let temp_dir = TempDir::new();
+        let config_path = temp_dir.path().join("config.toml");
+
+        assert!(CliSettings::try_parse_from(["bltui", "--set", "mouse"]).is_err());
+        let cli =
+            CliSettings::try_parse_from(["bltui", "-o", "mouse=true", "--set", "max_fps=fast"])
+                .unwrap();
+        assert!(matches!(
+            Config::load(&config_path, cli.overrides()),
+            Err(Error::InvalidInput(message)) if message.ends_with("setting `max_fps`")
+        ));
+        let cli = CliSettings::try_parse_from(["bltui", "--set", "mouse.x=true"]).unwrap();
+        assert!(Config::load(&config_path, cli.overrides()).is_err());
+    }
+
+    #[test]
+    fn test_cli_log_level() {
+        let cli = CliSettings::try_parse_from(["bltui", "--log-level", "warn"]).unwrap();
+        assert_eq!(cli.get_log_level(), Some(LogLevel::Warn));
+        let cli = CliSettings::try_parse_from(["bltui", "-dd"]).unwrap();
+        assert_eq!(cli.get_log_level(), Some(LogLevel::Trace));
+        assert!(CliSettings::try_parse_from(["bltui", "-d", "--log-level", "off"]).is_err());
+    }
+
+    #[test]
+    fn test_dump_has_every_setting() {
+        let config = Config {
+            max_fps: Some(12),
+            ..Default::default()
+        }
+        .with_defaults(PathBuf::from("/logs"));
+
+        let dump = config.dump().unwrap();
+        let dumped: Config = toml::from_str(&dump).unwrap();
+
+        assert_eq!(dumped.max_fps, Some(12));
+        assert_eq!(dumped.log_level, Some(LogLevel::Info));
+        assert_eq!(dumped.log_path, Some(PathBuf::from("/logs")));
+        assert_eq!(dumped.theme.preset.as_deref(), Some("dark"));
+        assert_eq!(dumped.layout.list_size, Some(40));
+        assert_eq!(dumped.logging.max_files, Some(10));
+        assert_eq!(dumped.keys.len(), Action::ALL.len());
+        assert!(Keymap::new(dumped.keys.into_iter().collect()).is_ok());
+    }
+
+    #[test]
+    fn test_default_config() {
+        let temp_dir = TempDir::new();
+        let config_path = temp_dir.path().join("config.toml");
+        init_config_file(&config_path, false).unwrap();
+        assert!(init_config_file(&config_path, false).is_err());
+        init_config_file(&config_path, true).unwrap();
+
+        let config = Config::read_from(&config_path).unwrap();
+        assert!(config.max_fps.is_none());
+
+        // Every setting of the template is valid once uncommented
+        let uncommented: String = DEFAULT_CONFIG
+            .lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| !line.starts_with(char::is_uppercase))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        std::fs::write(&config_path, uncommented).unwrap();
+
+        let config = Config::read_from(&config_path).unwrap();
+        assert_eq!(config.max_fps, Some(DEFAULT_MAX_FPS));
+        assert_eq!(config.hooks.len(), 1);
+        assert_eq!(config.logging.max_files, Some(10));
+    }
 }
@@ -0,0 +1,372 @@
+use std::path::PathBuf;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use toml_edit::value;
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+use crate::{
+    settings::{config_table, edit_config_file},
+    Error,
+};
+
+/// Terminals narrower than this stack the panes
+static NARROW_WIDTH: u16 = 90;
+/// Terminals at least this wide show the panes in three columns
+static WIDE_WIDTH: u16 = 180;
+static DEFAULT_LIST_SIZE: u16 = 40;
+static DEFAULT_DETAILS_SIZE: u16 = 50;
+static MIN_SIZE: u16 = 20;
+static MAX_SIZE: u16 = 80;
+/// Percentage added or removed by each resize
+pub static RESIZE_STEP: i16 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutPreset {
+    /// Every pane under the previous one
+    Stacked,
+    /// Devices on the left, details over the logger on the right
+    Split,
+    /// Devices, details and logger side by side
+    Columns,
+}
+
+impl LayoutPreset {
+    pub fn for_width(width: u16) -> LayoutPreset {
+        if width < NARROW_WIDTH {
+            LayoutPreset::Stacked
+        } else if width >= WIDE_WIDTH {
+            LayoutPreset::Columns
+        } else {
+            LayoutPreset::Split
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    Devices,
+    Details,
+    Logger,
+}
+
+/// `[layout]` table of the config file
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutConfig {
+    /// Chosen from the terminal width when unset
+    pub preset: Option<LayoutPreset>,
+    /// Percentage of the screen taken by the devices list
+    pub list_size: Option<u16>,
+    /// Percentage of the remaining space taken by the details
+    pub details_size: Option<u16>,
+    pub show_logger: Option<bool>,
+}
+
+impl LayoutConfig {
+    /// Every setting but the preset, with the defaults of the unset ones
+    pub fn with_defaults(self) -> LayoutConfig {
+        LayoutConfig {
+            preset: self.preset,
+            list_size: self.list_size.or(Some(DEFAULT_LIST_SIZE)),
+            details_size: self.details_size.or(Some(DEFAULT_DETAILS_SIZE)),
+            show_logger: self.show_logger.or(Some(true)),
+        }
+    }
+}
+
+/// Areas of the screen for each pane. Hidden panes get an empty area
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Areas {
+    pub title: Rect,
+    pub devices: Rect,
+    pub details: Rect,
+    pub logger: Rect,
+    pub commands: Rect,
+}
+
+#[derive(Debug)]
+pub struct PaneLayout {
+    config_file: Option<PathBuf>,
+    pub preset: Option<LayoutPreset>,
+    pub list_size: u16,
+    pub details_size: u16,
+    pub show_logger: bool,
+    /// Pane shown full screen, not saved
+    pub zoom: Option<Pane>,
+}
+
+impl Default for PaneLayout {
+    fn default() -> PaneLayout {
+        PaneLayout {
+            config_file: None,
+            preset: None,
+            list_size: DEFAULT_LIST_SIZE,
+            details_size: DEFAULT_DETAILS_SIZE,
+            show_logger: true,
+            zoom: None,
+        }
+    }
+}
+
+fn clamp_size(size: u16) -> u16 {
+    size.clamp(MIN_SIZE, MAX_SIZE)
+}
+
+/// Split `area` in two, the first part taking `percentage` of it
+fn split(area: Rect, direction: Direction, percentage: u16) -> (Rect, Rect) {
+    let chunks = Layout::default()
+        .direction(direction)
+        .constraints([
+            Constraint::Percentage(percentage),
+            Constraint::Percentage(100 - percentage),
+        ])
+        .split(area);
+    (chunks[0], chunks[1])
+}
+
+/// Take the rows of the operations pane from the bottom of the devices
+/// list, returning the list and the pane
+pub fn split_operations(devices: Rect, operations: usize) -> (Rect, Rect) {
+    if operations == 0 || devices.area() == 0 {
+        return (devices, Rect::default());
+    }
+
+    let height = (operations as u16 + 2).min(devices.height / 2);
+    (
+        Rect {
+            height: devices.height - height,
+            ..devices
+        },
+        Rect {
+            y: devices.bottom() - height,
+            height,
+            ..devices
+        },
+    )
+}
+
+impl PaneLayout {
+    pub fn new(config: LayoutConfig, config_file: PathBuf) -> PaneLayout {
+        PaneLayout {
+            config_file: Some(config_file),
+            preset: config.preset,
+            list_size: clamp_size(config.list_size.unwrap_or(DEFAULT_LIST_SIZE)),
+            details_size: clamp_size(config.details_size.unwrap_or(DEFAULT_DETAILS_SIZE)),
+            show_logger: config.show_logger.unwrap_or(true),
+            zoom: None,
+        }
+    }
+
+    pub fn resize_list(&mut self, delta: i16) {
+        self.list_size = clamp_size(self.list_size.saturating_add_signed(delta));
+    }
+
+    pub fn resize_details(&mut self, delta: i16) {
+        self.details_size = clamp_size(self.details_size.saturating_add_signed(delta));
+    }
+
+    pub fn toggle_logger(&mut self) {
+        self.show_logger = !self.show_logger;
+        if self.zoom == Some(Pane::Logger) {
+            self.zoom = None;
+        }
+    }
+
+    /// Zoom on the next pane, then back to every pane
+    pub fn cycle_zoom(&mut self) {
+        self.zoom = match self.zoom {
+            None => Some(Pane::Devices),
+            Some(Pane::Devices) => Some(Pane::Details),
+            Some(Pane::Details) if self.show_logger => Some(Pane::Logger),
+            Some(Pane::Details) | Some(Pane::Logger) => None,
+        };
+    }
+
+    pub fn areas(&self, size: Rect) -> Areas {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(5),
+                Constraint::Length(3),
+            ])
+            .split(size);
+        let mut areas = Areas {
+            title: chunks[0],
+            commands: chunks[2],
+            ..Areas::default()
+        };
+        let main = chunks[1];
+
+        if let Some(pane) = self.zoom {
+            match pane {
+                Pane::Devices => areas.devices = main,
+                Pane::Details => areas.details = main,
+                Pane::Logger => areas.logger = main,
+            }
+            return areas;
+        }
+
+        let preset = self
+            .preset
+            .unwrap_or_else(|| LayoutPreset::for_width(size.width));
+        let direction = match preset {
+            LayoutPreset::Stacked => Direction::Vertical,
+            LayoutPreset::Split | LayoutPreset::Columns => Direction::Horizontal,
+        };
+        let (devices, rest) = split(main, direction, self.list_size);
+        areas.devices = devices;
+
+        if !self.show_logger {
+            areas.details = rest;
+            return areas;
+        }
+
+        let direction = match preset {
+            LayoutPreset::Stacked | LayoutPreset::Split => Direction::Vertical,
+            LayoutPreset::Columns => Direction::Horizontal,
+        };
+        (areas.details, areas.logger) = split(rest, direction, self.details_size);
+
+        areas
+    }
+
+    /// Save the sizes and the logger visibility in the config file
+    pub fn save(&self) -> Result<(), Error> {
+        let Some(config_file) = &self.config_file else {
+            return Ok(());
+        };
+
+        edit_config_file(config_file, |document| {
+            let layout = config_table(document, "layout")?;
+            layout["list_size"] = value(self.list_size as i64);
+            layout["details_size"] = value(self.details_size as i64);
+            layout["show_logger"] = value(self.show_logger);
+            Some(())
+        })?;
+        debug!("Saved the layout to {:?}", config_file);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_preset_for_width() {
+        assert_eq!(LayoutPreset::for_width(60), LayoutPreset::Stacked);
+        assert_eq!(LayoutPreset::for_width(120), LayoutPreset::Split);
+        assert_eq!(LayoutPreset::for_width(200), LayoutPreset::Columns);
+    }
+
+    #[test]
+    fn test_split_areas() {
+        let layout = PaneLayout::default();
+        let areas = layout.areas(Rect::new(0, 0, 100, 40));
+
+        assert_eq!(areas.title, Rect::new(0, 0, 100, 3));
+        assert_eq!(areas.devices, Rect::new(0, 3, 40, 34));
+        assert_eq!(areas.details, Rect::new(40, 3, 60, 17));
+        assert_eq!(areas.logger, Rect::new(40, 20, 60, 17));
+        assert_eq!(areas.commands, Rect::new(0, 37, 100, 3));
+    }
+
+    #[test]
+    fn test_stacked_and_columns_areas() {
+        let layout = PaneLayout::default();
+
+        let areas = layout.areas(Rect::new(0, 0, 60, 40));
+        assert_eq!(areas.devices.width, 60);
+        assert!(areas.devices.bottom() <= areas.details.top());
+        assert!(areas.details.bottom() <= areas.logger.top());
+
+        let areas = layout.areas(Rect::new(0, 0, 200, 40));
+        assert_eq!(areas.devices.height, 34);
+        assert!(areas.devices.right() <= areas.details.left());
+        assert!(areas.details.right() <= areas.logger.left());
+    }
+
+    #[test]
+    fn test_hidden_logger_and_zoom() {
+        let mut layout = PaneLayout::default();
+        layout.toggle_logger();
+
+        let areas = layout.areas(Rect::new(0, 0, 100, 40));
+        assert_eq!(areas.details, Rect::new(40, 3, 60, 34));
+        assert_eq!(areas.logger, Rect::default());
+
+        layout.cycle_zoom();
+        layout.cycle_zoom();
+        assert_eq!(layout.zoom, Some(Pane::Details));
+        let areas = layout.areas(Rect::new(0, 0, 100, 40));
+        assert_eq!(areas.details, Rect::new(0, 3, 100, 34));
+        assert_eq!(areas.devices, Rect::default());
+
+        // The hidden logger is skipped
+        layout.cycle_zoom();
+        assert_eq!(layout.zoom, None);
+    }
+
+    #[test]
+    fn test_split_operations() {
+        let devices = Rect::new(0, 3, 40, 34);
+
+        assert_eq!(split_operations(devices, 0), (devices, Rect::default()));
+        assert_eq!(
+            split_operations(devices, 2),
+            (Rect::new(0, 3, 40, 30), Rect::new(0, 33, 40, 4))
+        );
+        // At most half of the list
+        assert_eq!(
+            split_operations(devices, 30),
+            (Rect::new(0, 3, 40, 17), Rect::new(0, 20, 40, 17))
+        );
+    }
+
+    #[test]
+    fn test_resize_is_clamped() {
+        let mut layout = PaneLayout::default();
+        for _ in 0..20 {
+            layout.resize_list(RESIZE_STEP);
+            layout.resize_details(-RESIZE_STEP);
+        }
+
+        assert_eq!(layout.list_size, MAX_SIZE);
+        assert_eq!(layout.details_size, MIN_SIZE);
+    }
+
+    #[test]
+    fn test_save_keeps_config() {
+        let mut folder = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        folder.push("temp");
+        folder.push(format!(
+            "layout-{}",
+            rand::thread_rng().gen_range(0..100000)
+        ));
+        std::fs::create_dir_all(&folder).unwrap();
+        let config_file = folder.join("config.toml");
+        std::fs::write(&config_file, "# My adapter\nadapter = \"hci0\"\n").unwrap();
+
+        let mut layout = PaneLayout::new(LayoutConfig::default(), config_file.clone());
+        layout.resize_list(RESIZE_STEP);
+        layout.toggle_logger();
+        layout.save().unwrap();
+
+        let content = std::fs::read_to_string(&config_file).unwrap();
+        std::fs::remove_dir_all(&folder).unwrap();
+
+        assert!(content.starts_with("# My adapter\nadapter = \"hci0\"\n"));
+        let config: LayoutConfig = toml::from_str::<toml::Value>(&content).unwrap()["layout"]
+            .clone()
+            .try_into()
+            .unwrap();
+        assert_eq!(config.list_size, Some(45));
+        assert_eq!(config.details_size, Some(50));
+        assert_eq!(config.show_logger, Some(false));
+    }
+}
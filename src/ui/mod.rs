@@ -1,27 +1,57 @@
-use std::io::Stdout;
+use std::{cmp::min, io::Stdout};
 
-use crossterm::{execute, terminal::EnterAlternateScreen};
+use crossterm::{
+    event::{EnableBracketedPaste, EnableFocusChange, EnableMouseCapture},
+    execute,
+    terminal::EnterAlternateScreen,
+};
 use log::debug;
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
+    layout::Rect,
+    widgets::Clear,
     Terminal,
 };
 
+pub mod layout;
+pub mod mouse;
+pub mod terminal;
+pub mod theme;
 pub mod widgets;
 
+#[cfg(test)]
+mod snapshots;
+
 use crate::App;
 
-use self::widgets::{
-    device_details::get_device_details,
-    devices::devices_list,
-    logger::get_logger_widget,
-    statics::{main_commands, popup_commands, title},
+use self::{
+    layout::split_operations,
+    mouse::UiLayout,
+    widgets::{
+        device_details::get_device_details,
+        devices::devices_list,
+        help::help_widget,
+        logger::{get_focused_logger_widget, get_logger_widget},
+        operations::operations_list,
+        statics::{
+            input_commands, logger_commands, main_commands, palette_commands, popup_commands, title,
+        },
+    },
 };
 
-pub fn initialize_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>, std::io::Error> {
+pub fn initialize_terminal(
+    mouse: bool,
+) -> Result<Terminal<CrosstermBackend<Stdout>>, std::io::Error> {
     let mut stdout = std::io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableBracketedPaste,
+        EnableFocusChange
+    )?;
+    if mouse {
+        execute!(stdout, EnableMouseCapture)?;
+    }
     crossterm::terminal::enable_raw_mode()?;
     let backend = tui::backend::CrosstermBackend::new(stdout);
     let mut terminal = tui::Terminal::new(backend)?;
@@ -30,6 +60,18 @@ pub fn initialize_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>, std::
     Ok(terminal)
 }
 
+/// Chunk in the middle of the screen, 60% wide
+fn centered_chunk(size: Rect, height: u16) -> Rect {
+    let height = min(height, size.height);
+    let width = size.width * 60 / 100;
+    Rect::new(
+        size.x + (size.width - width) / 2,
+        size.y + (size.height - height) / 2,
+        width,
+        height,
+    )
+}
+
 pub async fn draw_frame<B: Backend>(terminal: &mut Terminal<B>, app: &mut App, scanning: bool) {
     let selected_device = app.devices.get_selected_device().await;
 
@@ -37,57 +79,101 @@ pub async fn draw_frame<B: Backend>(terminal: &mut Terminal<B>, app: &mut App, s
         .draw(|rect| {
             let size = rect.size();
 
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(3),
-                    Constraint::Min(5),
-                    Constraint::Length(3),
-                ])
-                .split(size);
+            let mut areas = app.panes.areas(size);
+            let operations_area;
+            (areas.devices, operations_area) =
+                split_operations(areas.devices, app.operations.len());
 
-            let main_chunks = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
-                .split(chunks[1]);
+            app.layout = UiLayout {
+                devices: areas.devices,
+                details: areas.details,
+                logger: areas.logger,
+                popup: app.popup.as_ref().map(|_| centered_chunk(size, 6)),
+            };
+            app.devices
+                .update_offset(areas.devices.height.saturating_sub(2) as usize);
 
-            let right_chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-                .split(main_chunks[1]);
-
-            rect.render_widget(title(), chunks[0]);
-            rect.render_stateful_widget(
-                devices_list(&app.devices),
-                main_chunks[0],
-                &mut app.devices.list_state,
-            );
-            rect.render_widget(get_logger_widget(), right_chunks[1]);
-            rect.render_widget(get_device_details(selected_device), right_chunks[0]);
+            rect.render_widget(title(&app.theme), areas.title);
+            if areas.devices.area() > 0 {
+                rect.render_stateful_widget(
+                    devices_list(&app.theme, &app.devices, &app.operations),
+                    areas.devices,
+                    &mut app.devices.list_state,
+                );
+            }
+            if operations_area.area() > 0 {
+                rect.render_widget(
+                    operations_list(&app.theme, &app.operations),
+                    operations_area,
+                );
+            }
+            if areas.logger.area() == 0 {
+                app.logger_focused = false;
+            } else if app.logger_focused {
+                rect.render_widget(
+                    get_focused_logger_widget(&app.theme, &app.logger_state),
+                    areas.logger,
+                );
+            } else {
+                rect.render_widget(
+                    get_logger_widget(&app.theme, &app.logger_state),
+                    areas.logger,
+                );
+            }
+            if areas.details.area() > 0 {
+                rect.render_widget(
+                    get_device_details(&app.theme, selected_device, &app.autoconnect, &app.cache),
+                    areas.details,
+                );
+            }
+            if !app.toasts.is_empty() {
+                // Between the title and the command bar
+                let main = Rect::new(
+                    size.x,
+                    areas.title.bottom(),
+                    size.width,
+                    areas.commands.top() - areas.title.bottom(),
+                );
+                rect.render_widget(app.toasts.get_widget(&app.theme), app.toasts.area(main));
+            }
 
             if let Some(popup) = &app.popup {
-                rect.render_widget(popup_commands(), chunks[2]);
-                let vertical_chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints([
-                        Constraint::Percentage(45),
-                        Constraint::Length(6),
-                        Constraint::Percentage(45),
-                    ])
-                    .split(size);
-
-                let popup_chunk = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .constraints([
-                        Constraint::Percentage(20),
-                        Constraint::Percentage(60),
-                        Constraint::Percentage(20),
-                    ])
-                    .split(vertical_chunks[1])[1];
-
-                rect.render_widget(popup.get_widget(), popup_chunk);
+                rect.render_widget(
+                    popup_commands(&app.theme, &app.keymap, areas.commands.width),
+                    areas.commands,
+                );
+                rect.render_widget(popup.get_widget(&app.theme), centered_chunk(size, 6));
+            } else if let Some(input_popup) = &app.input_popup {
+                rect.render_widget(
+                    input_commands(&app.theme, &app.keymap, areas.commands.width),
+                    areas.commands,
+                );
+                rect.render_widget(input_popup.get_widget(&app.theme), centered_chunk(size, 3));
+            } else if let Some(palette) = &app.palette {
+                rect.render_widget(
+                    palette_commands(&app.theme, &app.keymap, areas.commands.width),
+                    areas.commands,
+                );
+                let height = palette.matches().len() as u16 + 3;
+                rect.render_widget(palette.get_widget(&app.theme), centered_chunk(size, height));
+            } else if app.show_help {
+                rect.render_widget(
+                    main_commands(&app.theme, scanning, &app.keymap, areas.commands.width),
+                    areas.commands,
+                );
+                let help_chunk = centered_chunk(size, size.height * 8 / 10);
+                rect.render_widget(Clear, help_chunk);
+                rect.render_widget(help_widget(&app.theme, &app.keymap), help_chunk);
+            } else if app.logger_focused {
+                rect.render_widget(
+                    logger_commands(&app.theme, &app.keymap, areas.commands.width),
+                    areas.commands,
+                );
             } else {
-                rect.render_widget(main_commands(scanning), chunks[2]);
+                rect.render_widget(
+                    main_commands(&app.theme, scanning, &app.keymap, areas.commands.width),
+                    areas.commands,
+                );
             }
         })
         .unwrap();
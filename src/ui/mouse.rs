@@ -0,0 +1,122 @@
+use std::time::{Duration, Instant};
+
+use tui::layout::{Margin, Rect};
+
+/// Two clicks on the same cell within this delay make a double-click
+static DOUBLE_CLICK_DELAY: Duration = Duration::from_millis(400);
+
+fn contains(rect: Rect, column: u16, row: u16) -> bool {
+    column >= rect.left() && column < rect.right() && row >= rect.top() && row < rect.bottom()
+}
+
+/// Areas of the last drawn frame, used to find what the mouse points at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UiLayout {
+    pub devices: Rect,
+    pub details: Rect,
+    pub logger: Rect,
+    /// Area of the yes/no popup, when one is shown
+    pub popup: Option<Rect>,
+}
+
+impl UiLayout {
+    pub fn in_devices(&self, column: u16, row: u16) -> bool {
+        contains(self.devices, column, row)
+    }
+
+    pub fn in_logger(&self, column: u16, row: u16) -> bool {
+        contains(self.logger, column, row)
+    }
+
+    /// Index of the devices list row under the mouse, given the index
+    /// of the first visible row
+    pub fn device_row(&self, column: u16, row: u16, offset: usize) -> Option<usize> {
+        let list = self.devices.inner(&Margin {
+            vertical: 1,
+            horizontal: 1,
+        });
+        if contains(list, column, row) {
+            Some(offset + (row - list.top()) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// crossterm only reports single clicks, double-clicks are detected
+/// from their timing
+#[derive(Debug, Default)]
+pub struct ClickTracker {
+    last: Option<(Instant, u16, u16)>,
+}
+
+impl ClickTracker {
+    /// Record a click, returning whether it completes a double-click
+    pub fn click(&mut self, column: u16, row: u16) -> bool {
+        self.click_at(Instant::now(), column, row)
+    }
+
+    fn click_at(&mut self, now: Instant, column: u16, row: u16) -> bool {
+        match self.last.take() {
+            Some((at, last_column, last_row))
+                if now.duration_since(at) <= DOUBLE_CLICK_DELAY
+                    && (last_column, last_row) == (column, row) =>
+            {
+                true
+            }
+            _ => {
+                self.last = Some((now, column, row));
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> UiLayout {
+        UiLayout {
+            devices: Rect::new(0, 3, 40, 20),
+            details: Rect::new(40, 3, 60, 10),
+            logger: Rect::new(40, 13, 60, 10),
+            popup: None,
+        }
+    }
+
+    #[test]
+    fn test_device_row() {
+        let layout = layout();
+
+        // Borders are not rows
+        assert_eq!(layout.device_row(10, 3, 0), None);
+        assert_eq!(layout.device_row(0, 5, 0), None);
+        assert_eq!(layout.device_row(10, 4, 0), Some(0));
+        assert_eq!(layout.device_row(10, 6, 5), Some(7));
+        assert_eq!(layout.device_row(50, 6, 0), None);
+    }
+
+    #[test]
+    fn test_areas() {
+        let layout = layout();
+
+        assert!(layout.in_devices(39, 22));
+        assert!(!layout.in_devices(40, 22));
+        assert!(layout.in_logger(40, 13));
+        assert!(!layout.in_logger(40, 12));
+    }
+
+    #[test]
+    fn test_double_click() {
+        let mut clicks = ClickTracker::default();
+        let now = Instant::now();
+
+        assert!(!clicks.click_at(now, 1, 1));
+        assert!(clicks.click_at(now + Duration::from_millis(100), 1, 1));
+        // A third click starts over
+        assert!(!clicks.click_at(now + Duration::from_millis(200), 1, 1));
+        assert!(!clicks.click_at(now + Duration::from_millis(300), 2, 1));
+        assert!(!clicks.click_at(now + Duration::from_secs(1), 2, 1));
+    }
+}
@@ -0,0 +1,317 @@
+use std::path::PathBuf;
+
+use btleplug::platform::PeripheralId;
+use serde::Deserialize;
+use tokio::sync::oneshot;
+use tui::{
+    backend::TestBackend,
+    buffer::{Buffer, Cell},
+    style::{Color, Modifier},
+    Frame, Terminal,
+};
+
+use crate::{
+    bluetooth::{
+        autoconnect::AutoConnect, cache::DeviceCache, devices::Device, operations::Operations,
+    },
+    keymap::Keymap,
+    ui::{
+        draw_frame,
+        theme::Theme,
+        widgets::{
+            device_details::get_device_details, devices::devices_list, popup::YesNoPopup,
+            statics::main_commands,
+        },
+    },
+    App,
+};
+
+/// Set to write the snapshots again instead of comparing them
+static UPDATE_VAR: &str = "BLTUI_UPDATE_SNAPSHOTS";
+/// Keys of the styles in the styled snapshots, `.` being the default style
+static STYLE_KEYS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+/// Stacked, split and three columns layouts
+static FRAME_SIZES: [(u16, u16); 3] = [(60, 20), (120, 30), (200, 40)];
+
+#[derive(Deserialize)]
+struct Id {
+    periph_id: PeripheralId,
+}
+
+fn device(address: &str, name: &str, connected: bool, paired: bool) -> Device {
+    let id: Id = toml::from_str(&format!(
+        "periph_id = {{ object_path = \"/org/bluez/hci0/dev_{}\" }}",
+        address.replace(':', "_")
+    ))
+    .unwrap();
+    Device {
+        periph_id: id.periph_id,
+        address: address.to_string(),
+        name: name.to_string(),
+        alias: None,
+        connected,
+        paired,
+        trusted: paired,
+        rssi: Some(-60),
+        tx_power: None,
+    }
+}
+
+fn devices() -> Vec<Device> {
+    vec![
+        device("AA:BB:CC:DD:EE:FF", "Headphones", true, true),
+        device("11:22:33:44:55:66", "Keyboard", false, true),
+        device("66:55:44:33:22:11", "Unknown", false, false),
+    ]
+}
+
+fn app() -> App {
+    let mut app = App::new();
+    app.devices.show_unknown = true;
+    // Not through the cache, which would show when the devices were seen
+    for device in devices() {
+        app.devices.insert_or_replace(device);
+    }
+    app.devices.move_selector_down();
+    app
+}
+
+fn render(width: u16, height: u16, draw: impl FnOnce(&mut Frame<TestBackend>)) -> Buffer {
+    let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+    terminal.draw(draw).unwrap();
+    terminal.backend().buffer().clone()
+}
+
+/// Symbols of the buffer, without trailing spaces
+fn buffer_text(buffer: &Buffer) -> String {
+    let area = buffer.area;
+    (area.top()..area.bottom())
+        .map(|y| {
+            let line: String = (area.left()..area.right())
+                .map(|x| buffer.get(x, y).symbol.as_str())
+                .collect();
+            format!("{}\n", line.trim_end())
+        })
+        .collect()
+}
+
+/// Symbols of the buffer, followed by the style of each cell as a key
+/// and the legend of the keys
+fn styled_buffer_text(buffer: &Buffer) -> String {
+    let area = buffer.area;
+    let mut styles: Vec<(Color, Color, Modifier)> = Vec::new();
+    let mut grid = String::new();
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            let Cell {
+                fg, bg, modifier, ..
+            } = *buffer.get(x, y);
+            let key = if (fg, bg, modifier) == (Color::Reset, Color::Reset, Modifier::empty()) {
+                '.'
+            } else {
+                let index = styles
+                    .iter()
+                    .position(|style| *style == (fg, bg, modifier))
+                    .unwrap_or_else(|| {
+                        styles.push((fg, bg, modifier));
+                        styles.len() - 1
+                    });
+                STYLE_KEYS
+                    .chars()
+                    .nth(index)
+                    .expect("Too many styles for a snapshot")
+            };
+            grid.push(key);
+        }
+        grid.push('\n');
+    }
+
+    let legend: String = STYLE_KEYS
+        .chars()
+        .zip(&styles)
+        .map(|(key, (fg, bg, modifier))| {
+            format!("{} = fg {:?}, bg {:?}, {:?}\n", key, fg, bg, modifier)
+        })
+        .collect();
+    format!("{}\n{}\n{}", buffer_text(buffer), grid, legend)
+}
+
+/// Compare the symbols of the buffer to the stored snapshot `name`, or
+/// store them when updating the snapshots
+fn assert_snapshot(name: &str, buffer: &Buffer) {
+    assert_snapshot_text(name, buffer_text(buffer));
+}
+
+/// Same as `assert_snapshot`, with the styles of the cells
+fn assert_styled_snapshot(name: &str, buffer: &Buffer) {
+    assert_snapshot_text(name, styled_buffer_text(buffer));
+}
+
+fn assert_snapshot_text(name: &str, actual: String) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/ui/snapshots")
+        .join(format!("{}.txt", name));
+
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "Missing snapshot {:?}, run the tests with {}=1 to create it",
+            path, UPDATE_VAR
+        )
+    });
+    if let Some((line, (expected_line, actual_line))) = expected
+        .lines()
+        .zip(actual.lines())
+        .enumerate()
+        .find(|(_, (expected_line, actual_line))| expected_line != actual_line)
+    {
+        panic!(
+            "Snapshot {} differs at line {}, run the tests with {}=1 to update it\nexpected : {:?}\nactual   : {:?}\n\n{}",
+            name,
+            line + 1,
+            UPDATE_VAR,
+            expected_line,
+            actual_line,
+            actual
+        );
+    }
+    assert_eq!(
+        expected.lines().count(),
+        actual.lines().count(),
+        "Snapshot {} has a different height",
+        name
+    );
+}
+
+#[tokio::test]
+async fn test_frame() {
+    for (width, height) in FRAME_SIZES {
+        let mut app = app();
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+
+        draw_frame(&mut terminal, &mut app, false).await;
+
+        assert_snapshot(
+            &format!("frame_{}x{}", width, height),
+            terminal.backend().buffer(),
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_frame_with_pin_and_popup() {
+    let mut app = app();
+    app.devices.move_selector_down();
+    app.devices.toggle_selected_pin();
+    let (tx, _rx) = oneshot::channel();
+    app.popup = Some(YesNoPopup::new(
+        "Confirm pairing with passkey 123456".to_string(),
+        tx,
+    ));
+    let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+
+    draw_frame(&mut terminal, &mut app, true).await;
+
+    assert_snapshot("frame_popup_120x30", terminal.backend().buffer());
+}
+
+#[tokio::test]
+async fn test_frame_styles() {
+    for preset in Theme::PRESETS {
+        let mut app = app();
+        app.theme = Theme::preset(preset).unwrap();
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+
+        draw_frame(&mut terminal, &mut app, false).await;
+
+        assert_styled_snapshot(
+            &format!("frame_styles_{}", preset),
+            terminal.backend().buffer(),
+        );
+    }
+}
+
+#[test]
+fn test_devices_list() {
+    let theme = Theme::default();
+    let mut app = app();
+
+    for (width, height) in [(30, 6), (80, 10)] {
+        let buffer = render(width, height, |frame| {
+            frame.render_stateful_widget(
+                devices_list(&theme, &app.devices, &Operations::default()),
+                frame.size(),
+                &mut app.devices.list_state,
+            )
+        });
+        assert_snapshot(&format!("devices_{}x{}", width, height), &buffer);
+    }
+}
+
+#[test]
+fn test_device_details() {
+    let theme = Theme::default();
+    let device = device("AA:BB:CC:DD:EE:FF", "Headphones", true, true);
+    let autoconnect = AutoConnect::new(vec![device.address.clone()], None);
+    let cache = DeviceCache::default();
+
+    for (width, height) in [(30, 8), (60, 14)] {
+        let buffer = render(width, height, |frame| {
+            frame.render_widget(
+                get_device_details(&theme, Some(device.clone()), &autoconnect, &cache),
+                frame.size(),
+            )
+        });
+        assert_snapshot(&format!("details_{}x{}", width, height), &buffer);
+    }
+
+    let buffer = render(30, 4, |frame| {
+        frame.render_widget(
+            get_device_details(&theme, None, &autoconnect, &cache),
+            frame.size(),
+        )
+    });
+    assert_snapshot("details_none", &buffer);
+}
+
+#[test]
+fn test_yes_no_popup() {
+    let theme = Theme::default();
+    let (tx, _rx) = oneshot::channel();
+    let mut popup = YesNoPopup::new("Accept pairing authorization ?".to_string(), tx);
+    popup.move_selector_down();
+    popup.move_selector_down();
+
+    for (width, height) in [(30, 6), (60, 6)] {
+        let buffer = render(width, height, |frame| {
+            frame.render_widget(popup.get_widget(&theme), frame.size())
+        });
+        assert_snapshot(&format!("popup_{}x{}", width, height), &buffer);
+    }
+}
+
+#[test]
+fn test_main_commands() {
+    let theme = Theme::default();
+    let keymap = Keymap::default();
+
+    for (width, scanning) in [(60, false), (160, false), (160, true)] {
+        let buffer = render(width, 3, |frame| {
+            frame.render_widget(
+                main_commands(&theme, scanning, &keymap, width),
+                frame.size(),
+            )
+        });
+        let name = if scanning {
+            format!("commands_scanning_{}", width)
+        } else {
+            format!("commands_{}", width)
+        };
+        assert_snapshot(&name, &buffer);
+    }
+}
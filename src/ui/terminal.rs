@@ -0,0 +1,82 @@
+use std::{
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crossterm::{
+    cursor::Show,
+    event::{DisableBracketedPaste, DisableFocusChange, DisableMouseCapture},
+    execute,
+    terminal::{disable_raw_mode, LeaveAlternateScreen},
+};
+
+use crate::events::AppEvent;
+
+/// Set once the terminal was restored, by the guard or the panic hook
+static RESTORED: AtomicBool = AtomicBool::new(false);
+
+/// Whether the terminal was restored, after which nothing can be drawn
+pub fn terminal_restored() -> bool {
+    RESTORED.load(Ordering::SeqCst)
+}
+
+/// Leave raw mode and the alternate screen, only the first time it is
+/// called
+fn restore(mouse: bool) -> Result<(), std::io::Error> {
+    if RESTORED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let mut stdout = std::io::stdout();
+    // Restore as much as possible even if a step fails
+    let raw_mode = disable_raw_mode();
+    if mouse {
+        let _ = execute!(stdout, DisableMouseCapture);
+    }
+    execute!(
+        stdout,
+        DisableFocusChange,
+        DisableBracketedPaste,
+        LeaveAlternateScreen,
+        Show
+    )?;
+    stdout.flush()?;
+
+    raw_mode
+}
+
+/// Restores the terminal when dropped, and before a panic message is
+/// printed. A panic anywhere stops the app, since the UI can't be drawn
+/// once the terminal was restored
+pub struct TerminalGuard {
+    mouse: bool,
+}
+
+impl TerminalGuard {
+    /// Create the guard before setting the terminal up, so a failure in
+    /// between still restores it. Panics are sent to `tx` so that the
+    /// app shuts down as usual, and only exit right away when the app
+    /// can't be told
+    pub fn new(mouse: bool, tx: tokio::sync::mpsc::Sender<AppEvent>) -> TerminalGuard {
+        let previous_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = restore(mouse);
+            previous_hook(info);
+            if tx.try_send(AppEvent::Panicked).is_err() {
+                std::process::exit(101);
+            }
+        }));
+
+        TerminalGuard { mouse }
+    }
+
+    pub fn restore(self) -> Result<(), std::io::Error> {
+        restore(self.mouse)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = restore(self.mouse);
+    }
+}
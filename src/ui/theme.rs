@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tui::style::{Color, Modifier, Style};
+
+use crate::Error;
+
+/// `[theme]` table of the config file
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub preset: Option<String>,
+    /// Overrides of the preset colours, by name
+    #[serde(default)]
+    pub colors: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub border: Color,
+    pub title: Color,
+    pub text: Color,
+    pub highlight_fg: Color,
+    pub highlight_bg: Color,
+    pub success: Color,
+    pub error: Color,
+    pub warning: Color,
+    pub muted: Color,
+    pub connected: Color,
+    pub paired: Color,
+    pub log_error: Color,
+    pub log_warn: Color,
+    pub log_info: Color,
+    pub log_debug: Color,
+    pub log_trace: Color,
+}
+
+impl Theme {
+    pub const PRESETS: [&'static str; 4] = ["dark", "light", "high-contrast", "monochrome"];
+
+    pub fn dark() -> Theme {
+        Theme {
+            border: Color::Blue,
+            title: Color::White,
+            text: Color::White,
+            highlight_fg: Color::Black,
+            highlight_bg: Color::White,
+            success: Color::Green,
+            error: Color::Red,
+            warning: Color::Yellow,
+            muted: Color::DarkGray,
+            connected: Color::Green,
+            paired: Color::Green,
+            log_error: Color::Red,
+            log_warn: Color::Yellow,
+            log_info: Color::White,
+            log_debug: Color::Gray,
+            log_trace: Color::Gray,
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            border: Color::Blue,
+            title: Color::Black,
+            text: Color::Black,
+            highlight_fg: Color::White,
+            highlight_bg: Color::Blue,
+            success: Color::Green,
+            error: Color::Red,
+            warning: Color::Magenta,
+            muted: Color::Gray,
+            connected: Color::Green,
+            paired: Color::Green,
+            log_error: Color::Red,
+            log_warn: Color::Magenta,
+            log_info: Color::Black,
+            log_debug: Color::DarkGray,
+            log_trace: Color::DarkGray,
+        }
+    }
+
+    pub fn high_contrast() -> Theme {
+        Theme {
+            border: Color::White,
+            title: Color::LightYellow,
+            text: Color::White,
+            highlight_fg: Color::Black,
+            highlight_bg: Color::LightYellow,
+            success: Color::LightGreen,
+            error: Color::LightRed,
+            warning: Color::LightYellow,
+            muted: Color::Gray,
+            connected: Color::LightGreen,
+            paired: Color::LightCyan,
+            log_error: Color::LightRed,
+            log_warn: Color::LightYellow,
+            log_info: Color::White,
+            log_debug: Color::Gray,
+            log_trace: Color::Gray,
+        }
+    }
+
+    /// Uses the terminal colours everywhere, the selection is shown
+    /// reversed
+    pub fn monochrome() -> Theme {
+        Theme {
+            border: Color::Reset,
+            title: Color::Reset,
+            text: Color::Reset,
+            highlight_fg: Color::Reset,
+            highlight_bg: Color::Reset,
+            success: Color::Reset,
+            error: Color::Reset,
+            warning: Color::Reset,
+            muted: Color::Reset,
+            connected: Color::Reset,
+            paired: Color::Reset,
+            log_error: Color::Reset,
+            log_warn: Color::Reset,
+            log_info: Color::Reset,
+            log_debug: Color::Reset,
+            log_trace: Color::Reset,
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            "monochrome" => Some(Theme::monochrome()),
+            _ => None,
+        }
+    }
+
+    /// Build the theme from the config, `no_color` forces the
+    /// monochrome preset (see https://no-color.org)
+    pub fn new(config: ThemeConfig, no_color: bool) -> Result<Theme, Error> {
+        if no_color {
+            return Ok(Theme::monochrome());
+        }
+
+        let mut theme = match config.preset.as_deref() {
+            Some(name) => Theme::preset(name).ok_or_else(|| {
+                Error::InvalidTheme(format!(
+                    "unknown preset `{}`, expected one of {}",
+                    name,
+                    Theme::PRESETS.join(", ")
+                ))
+            })?,
+            None => Theme::default(),
+        };
+
+        for (name, value) in config.colors {
+            let color = parse_color(&value)?;
+            *theme
+                .color_mut(&name)
+                .ok_or_else(|| Error::InvalidTheme(format!("unknown colour `{}`", name)))? = color;
+        }
+
+        Ok(theme)
+    }
+
+    fn color_mut(&mut self, name: &str) -> Option<&mut Color> {
+        match name {
+            "border" => Some(&mut self.border),
+            "title" => Some(&mut self.title),
+            "text" => Some(&mut self.text),
+            "highlight_fg" => Some(&mut self.highlight_fg),
+            "highlight_bg" => Some(&mut self.highlight_bg),
+            "success" => Some(&mut self.success),
+            "error" => Some(&mut self.error),
+            "warning" => Some(&mut self.warning),
+            "muted" => Some(&mut self.muted),
+            "connected" => Some(&mut self.connected),
+            "paired" => Some(&mut self.paired),
+            "log_error" => Some(&mut self.log_error),
+            "log_warn" => Some(&mut self.log_warn),
+            "log_info" => Some(&mut self.log_info),
+            "log_debug" => Some(&mut self.log_debug),
+            "log_trace" => Some(&mut self.log_trace),
+            _ => None,
+        }
+    }
+
+    pub fn text_style(&self) -> Style {
+        Style::default().fg(self.text)
+    }
+
+    pub fn highlight_style(&self) -> Style {
+        if self.highlight_fg == Color::Reset && self.highlight_bg == Color::Reset {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default().fg(self.highlight_fg).bg(self.highlight_bg)
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::dark()
+    }
+}
+
+/// Parse a colour name (`blue`, `light-red`, `dark-gray`...), an ANSI
+/// index (`0` to `255`) or a `#rrggbb` hex value
+pub fn parse_color(value: &str) -> Result<Color, Error> {
+    let invalid = || Error::InvalidTheme(format!("`{}` is not a valid colour", value));
+    let value_lower = value.trim().to_lowercase();
+
+    if let Some(hex) = value_lower.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+        return Ok(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+
+    if let Ok(index) = value_lower.parse::<u8>() {
+        return Ok(Color::Indexed(index));
+    }
+
+    let color = match value_lower.replace(['-', '_', ' '], "").as_str() {
+        "reset" | "default" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return Err(invalid()),
+    };
+
+    Ok(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("blue").unwrap(), Color::Blue);
+        assert_eq!(parse_color("Light-Red").unwrap(), Color::LightRed);
+        assert_eq!(parse_color("dark_grey").unwrap(), Color::DarkGray);
+        assert_eq!(parse_color("42").unwrap(), Color::Indexed(42));
+        assert_eq!(
+            parse_color("#1e90FF").unwrap(),
+            Color::Rgb(0x1e, 0x90, 0xff)
+        );
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("#gggggg").is_err());
+        assert!(parse_color("256").is_err());
+        assert!(parse_color("blurple").is_err());
+    }
+
+    #[test]
+    fn test_default_is_dark() {
+        let theme = Theme::new(ThemeConfig::default(), false).unwrap();
+
+        assert_eq!(theme, Theme::dark());
+    }
+
+    #[test]
+    fn test_preset_with_overrides() {
+        let config = ThemeConfig {
+            preset: Some("light".to_string()),
+            colors: BTreeMap::from([
+                ("border".to_string(), "magenta".to_string()),
+                ("log_info".to_string(), "#000000".to_string()),
+            ]),
+        };
+
+        let theme = Theme::new(config, false).unwrap();
+
+        assert_eq!(theme.border, Color::Magenta);
+        assert_eq!(theme.log_info, Color::Rgb(0, 0, 0));
+        assert_eq!(theme.text, Theme::light().text);
+    }
+
+    #[test]
+    fn test_unknown_preset_and_colour() {
+        let config = ThemeConfig {
+            preset: Some("solarized".to_string()),
+            colors: BTreeMap::new(),
+        };
+        assert!(Theme::new(config, false).is_err());
+
+        let config = ThemeConfig {
+            preset: None,
+            colors: BTreeMap::from([("background".to_string(), "red".to_string())]),
+        };
+        assert!(Theme::new(config, false).is_err());
+    }
+
+    #[test]
+    fn test_no_color() {
+        let config = ThemeConfig {
+            preset: Some("high-contrast".to_string()),
+            colors: BTreeMap::from([("border".to_string(), "red".to_string())]),
+        };
+
+        let theme = Theme::new(config, true).unwrap();
+
+        assert_eq!(theme, Theme::monochrome());
+        assert!(theme
+            .highlight_style()
+            .add_modifier
+            .contains(Modifier::REVERSED));
+    }
+}
@@ -5,17 +5,38 @@ use tui::{
     widgets::Paragraph,
 };
 
-use crate::bluetooth::devices::Device;
+use crate::{
+    bluetooth::{
+        autoconnect::AutoConnect,
+        cache::{format_timestamp, DeviceCache},
+        devices::Device,
+    },
+    ui::theme::Theme,
+};
+
+use super::statics::bordered_box;
 
-use super::{statics::blue_box, text_style};
+fn yes_no(value: bool, yes: Color, no: Color) -> Span<'static> {
+    if value {
+        Span::styled("yes", Style::default().fg(yes))
+    } else {
+        Span::styled("no", Style::default().fg(no))
+    }
+}
 
-pub fn get_device_details(selected_device: Option<Device>) -> Paragraph<'static> {
+pub fn get_device_details(
+    theme: &Theme,
+    selected_device: Option<Device>,
+    autoconnect: &AutoConnect,
+    cache: &DeviceCache,
+) -> Paragraph<'static> {
     let device_details_str = if let Some(device) = selected_device {
-        Text::from(vec![
+        let mut lines = vec![
             Spans::from(Span::styled(
-                device.name,
-                text_style().add_modifier(Modifier::BOLD),
+                device.display_name().to_string(),
+                theme.text_style().add_modifier(Modifier::BOLD),
             )),
+            Spans::from(Span::raw(format!("Name : {}", device.name))),
             Spans::from(Span::raw(format!("Address : {}", device.address))),
             Spans::from(Span::raw(format!(
                 "Signal strenth : {} dBm",
@@ -27,35 +48,67 @@ pub fn get_device_details(selected_device: Option<Device>) -> Paragraph<'static>
             ))),
             Spans::from(vec![
                 Span::raw("Connected : "),
-                if device.connected {
-                    Span::styled("yes", Style::default().fg(Color::Green))
+                yes_no(device.connected, theme.connected, theme.error),
+            ]),
+            Spans::from(vec![
+                Span::raw("Paired : "),
+                yes_no(device.paired, theme.paired, theme.error),
+            ]),
+            Spans::from(vec![
+                Span::raw("Trusted : "),
+                yes_no(device.trusted, theme.success, theme.error),
+            ]),
+            Spans::from(vec![
+                Span::raw("Favourite : "),
+                if autoconnect.is_favourite(&device.address) {
+                    Span::styled("yes", Style::default().fg(theme.success))
+                } else {
+                    Span::raw("no")
+                },
+                if autoconnect.is_paused(&device.address) {
+                    Span::styled(" (auto-connect paused)", Style::default().fg(theme.warning))
                 } else {
-                    Span::styled("no", Style::default().fg(Color::Red))
+                    Span::raw("")
                 },
             ]),
-            // Spans::from(vec![
-            //     Span::raw("Paired : "),
-            //     if device.paired {
-            //         Span::styled("yes", Style::default().fg(Color::Green))
-            //     } else {
-            //         Span::styled("no", Style::default().fg(Color::Red))
-            //     },
-            // ]),
-            // Spans::from(vec![
-            //     Span::raw("Trusted : "),
-            //     if device.trusted {
-            //         Span::styled("yes", Style::default().fg(Color::Green))
-            //     } else {
-            //         Span::styled("no", Style::default().fg(Color::Red))
-            //     },
-            // ]),
-        ])
+        ];
+
+        if let Some(cached) = cache.get(&device.address) {
+            lines.push(Spans::from(Span::raw(format!(
+                "First seen : {}",
+                format_timestamp(cached.first_seen)
+            ))));
+            lines.push(Spans::from(Span::raw(format!(
+                "Last seen : {}",
+                format_timestamp(cached.last_seen)
+            ))));
+        }
+
+        let history = autoconnect.history(&device.address);
+        if !history.is_empty() {
+            lines.push(Spans::from(Span::raw("Auto-connect attempts :")));
+            for attempt in history.iter().rev() {
+                lines.push(Spans::from(vec![
+                    Span::raw(format!("  {} ", attempt.time())),
+                    if let Some(error) = &attempt.error {
+                        Span::styled(
+                            format!("failed ({})", error),
+                            Style::default().fg(theme.error),
+                        )
+                    } else {
+                        Span::styled("ok", Style::default().fg(theme.success))
+                    },
+                ]));
+            }
+        }
+
+        Text::from(lines)
     } else {
         Text::from(vec![Spans::from(vec![Span::raw("")])])
     };
 
     Paragraph::new(device_details_str)
-        .style(text_style())
+        .style(theme.text_style())
         .alignment(Alignment::Left)
-        .block(blue_box(Some(String::from("Details"))))
+        .block(bordered_box(theme, Some(String::from("Details"))))
 }
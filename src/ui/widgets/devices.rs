@@ -1,43 +1,80 @@
 use tui::{
-    style::{Color, Style},
+    style::{Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::List,
+    widgets::{List, ListItem},
 };
 
-use crate::bluetooth::devices::{Device, Devices};
+use crate::{
+    bluetooth::{
+        devices::{Device, Devices, Row},
+        operations::Operations,
+    },
+    ui::theme::Theme,
+};
+
+use super::statics::bordered_box;
+
+fn device_text(theme: &Theme, device: &Device, status: Option<String>) -> Text<'static> {
+    let mut spans = vec![Span::from(if device.display_name() == "Unknown" {
+        format!("{} ({})", device.name, device.address)
+    } else {
+        device.display_name().to_string()
+    })];
+    if device.paired {
+        spans.push(Span::styled(" (Paired)", Style::default().fg(theme.paired)));
+    }
+    if device.connected {
+        spans.push(Span::styled(
+            " (Connected)",
+            Style::default().fg(theme.connected),
+        ));
+    }
+    if let Some(status) = status {
+        spans.push(Span::styled(
+            format!(" {}", status),
+            Style::default().fg(theme.warning),
+        ));
+    }
 
-use super::{statics::blue_box, text_style};
+    Text::from(Spans::from(spans))
+}
 
-impl From<Device> for Text<'_> {
-    fn from(device: Device) -> Text<'static> {
-        Text::from(vec![Spans::from(vec![
-            Span::from(if device.name == "Unknown" {
-                format!("{} ({})", device.name, device.address)
-            } else {
-                device.name
-            }),
-            Span::styled(
-                if device.connected { " (Connected)" } else { "" },
-                Style::default().fg(Color::Green),
-            ),
-        ])])
+fn row_text(theme: &Theme, operations: &Operations, row: Row) -> Text<'static> {
+    match row {
+        Row::Pinned(_, Some(device)) => {
+            let mut text = device_text(theme, device, operations.status(&device.address));
+            text.patch_style(Style::default().add_modifier(Modifier::BOLD));
+            text
+        }
+        Row::Pinned(pin, None) => Text::from(Spans::from(vec![
+            Span::styled(pin.name.clone(), Style::default().fg(theme.muted)),
+            Span::styled(" (Offline)", Style::default().fg(theme.muted)),
+        ])),
+        Row::Separator => Text::from(Span::styled(
+            "─".repeat(64),
+            Style::default().fg(theme.border),
+        )),
+        Row::Device(device) => device_text(theme, device, operations.status(&device.address)),
     }
 }
 
-pub fn devices_list<'a>(devices: &Devices) -> List<'a> {
-    List::new(devices.list_items())
-        .style(text_style())
-        .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+pub fn devices_list<'a>(theme: &Theme, devices: &Devices, operations: &Operations) -> List<'a> {
+    let items: Vec<ListItem> = devices
+        .rows()
+        .into_iter()
+        .map(|row| ListItem::new(row_text(theme, operations, row)))
+        .collect();
+
+    List::new(items)
+        .style(theme.text_style())
+        .highlight_style(theme.highlight_style())
         .highlight_symbol("->")
-        .block(blue_box(Some(format!(
-            "Devices ({}/{})",
-            {
-                if let Some(index) = devices.list_state.selected() {
-                    index + 1
-                } else {
-                    0
-                }
-            },
-            devices.len()
-        ))))
+        .block(bordered_box(
+            theme,
+            Some(format!(
+                "Devices ({}/{})",
+                devices.selected_position().map_or(0, |index| index + 1),
+                devices.device_rows_len()
+            )),
+        ))
 }
@@ -0,0 +1,56 @@
+use tui::{
+    style::{Modifier, Style},
+    text::{Span, Spans, Text},
+    widgets::{Paragraph, Wrap},
+};
+
+use crate::{
+    commands::specs,
+    keymap::{Context, Keymap},
+    ui::theme::Theme,
+};
+
+use super::statics::bordered_box;
+
+/// Every keybinding grouped by context, followed by the palette commands
+pub fn help_widget(theme: &Theme, keymap: &Keymap) -> Paragraph<'static> {
+    let section = |title: &str| {
+        Spans::from(Span::styled(
+            title.to_string(),
+            Style::default()
+                .fg(theme.title)
+                .add_modifier(Modifier::BOLD),
+        ))
+    };
+
+    let mut lines = Vec::new();
+    for context in Context::ALL {
+        lines.push(section(context.title()));
+        for (action, keys) in keymap.in_context(context) {
+            if keys.is_empty() {
+                continue;
+            }
+            lines.push(Spans::from(vec![
+                Span::raw(format!("  {:<16} ", keymap.keys_str(*action))),
+                Span::raw(action.description()),
+            ]));
+        }
+        lines.push(Spans::from(""));
+    }
+
+    lines.push(section("Commands"));
+    for spec in specs() {
+        lines.push(Spans::from(vec![
+            Span::raw(format!(
+                "  :{:<36} ",
+                format!("{} {}", spec.name, spec.args)
+            )),
+            Span::raw(spec.description),
+        ]));
+    }
+
+    Paragraph::new(Text::from(lines))
+        .style(theme.text_style())
+        .wrap(Wrap { trim: false })
+        .block(bordered_box(theme, Some("Help".to_string())))
+}
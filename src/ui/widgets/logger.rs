@@ -1,19 +1,51 @@
-use tui::style::{Color, Style};
-use tui_logger::TuiLoggerWidget;
+use tui::style::Style;
+use tui_logger::{TuiLoggerSmartWidget, TuiLoggerWidget, TuiWidgetState};
 
-use super::statics::blue_box;
+use crate::ui::theme::Theme;
 
-pub fn get_logger_widget() -> TuiLoggerWidget<'static> {
-    tui_logger::TuiLoggerWidget::default()
-        .block(blue_box(None))
-        .style_error(Style::default().fg(Color::Red))
-        .style_warn(Style::default().fg(Color::Yellow))
-        .style_info(Style::default().fg(Color::White))
-        .style_debug(Style::default().fg(Color::Gray))
-        .style_trace(Style::default().fg(Color::Gray))
+use super::statics::bordered_box;
+
+pub fn get_logger_widget(theme: &Theme, state: &TuiWidgetState) -> TuiLoggerWidget<'static> {
+    let mut widget = tui_logger::TuiLoggerWidget::default()
+        .block(bordered_box(theme, None))
+        .style(theme.text_style())
+        .style_error(Style::default().fg(theme.log_error))
+        .style_warn(Style::default().fg(theme.log_warn))
+        .style_info(Style::default().fg(theme.log_info))
+        .style_debug(Style::default().fg(theme.log_debug))
+        .style_trace(Style::default().fg(theme.log_trace))
         .output_level(Some(tui_logger::TuiLoggerLevelOutput::Long))
         .output_file(false)
         .output_target(false)
         .output_line(false)
+        .output_timestamp(Some("%F %H:%M:%S%.3f".to_string()));
+    widget.state(state);
+    widget
+}
+
+/// Logger with its target selector, shown while it has the focus
+pub fn get_focused_logger_widget(
+    theme: &Theme,
+    state: &TuiWidgetState,
+) -> TuiLoggerSmartWidget<'static> {
+    tui_logger::TuiLoggerSmartWidget::default()
+        .title_target("Targets")
+        .title_log("Logs")
+        .border_style(Style::default().fg(theme.title))
+        .style(theme.text_style())
+        .highlight_style(theme.highlight_style())
+        .style_show(theme.text_style())
+        .style_hide(Style::default().fg(theme.muted))
+        .style_off(Style::default().fg(theme.muted))
+        .style_error(Style::default().fg(theme.log_error))
+        .style_warn(Style::default().fg(theme.log_warn))
+        .style_info(Style::default().fg(theme.log_info))
+        .style_debug(Style::default().fg(theme.log_debug))
+        .style_trace(Style::default().fg(theme.log_trace))
+        .output_level(Some(tui_logger::TuiLoggerLevelOutput::Long))
+        .output_file(false)
+        .output_target(true)
+        .output_line(false)
         .output_timestamp(Some("%F %H:%M:%S%.3f".to_string()))
+        .state(state)
 }
@@ -1,11 +1,9 @@
-use tui::style::{Color, Style};
-
 pub(super) mod device_details;
 pub(super) mod devices;
+pub(super) mod help;
 pub(super) mod logger;
+pub(super) mod operations;
+pub mod palette;
 pub mod popup;
 pub mod statics;
-
-pub(self) fn text_style() -> Style {
-    Style::default().fg(Color::White)
-}
+pub mod toast;
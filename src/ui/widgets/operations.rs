@@ -0,0 +1,47 @@
+use tui::{
+    style::Style,
+    text::{Span, Spans},
+    widgets::{List, ListItem},
+};
+
+use crate::{
+    bluetooth::operations::{spinner_frame, Operations},
+    ui::theme::Theme,
+};
+
+use super::statics::bordered_box;
+
+pub fn operations_list<'a>(theme: &Theme, operations: &Operations) -> List<'a> {
+    let items: Vec<ListItem> = operations
+        .list()
+        .into_iter()
+        .map(|running| {
+            ListItem::new(Spans::from(vec![
+                Span::styled(
+                    spinner_frame(running.elapsed),
+                    Style::default().fg(theme.warning),
+                ),
+                Span::raw(format!(
+                    " {} {}",
+                    running.operation.progress(),
+                    running.name
+                )),
+                Span::styled(
+                    format!(
+                        " {}s/{}s",
+                        running.elapsed.as_secs(),
+                        running.operation.timeout().as_secs()
+                    ),
+                    Style::default().fg(theme.muted),
+                ),
+            ]))
+        })
+        .collect();
+
+    List::new(items)
+        .style(theme.text_style())
+        .block(bordered_box(
+            theme,
+            Some(format!("Operations ({})", operations.len())),
+        ))
+}
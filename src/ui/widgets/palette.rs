@@ -0,0 +1,139 @@
+use std::cmp::min;
+
+use tui::{
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{List, ListItem, ListState, Paragraph, StatefulWidget, Widget},
+};
+
+use crate::{
+    commands::{complete, Command, CommandSpec},
+    ui::theme::Theme,
+    Error,
+};
+
+use super::statics::bordered_box;
+
+/// Number of completions shown under the input
+pub const MAX_MATCHES: usize = 8;
+
+/// The `:` prompt, completing commands as they are typed
+#[derive(Default)]
+pub struct CommandPalette {
+    input: String,
+    selected: usize,
+}
+
+impl CommandPalette {
+    pub fn new() -> CommandPalette {
+        CommandPalette::default()
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+        self.selected = 0;
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+        self.selected = 0;
+    }
+
+    pub fn matches(&self) -> Vec<CommandSpec> {
+        let mut matches = complete(&self.input);
+        matches.truncate(MAX_MATCHES);
+        matches
+    }
+
+    pub fn move_selector_down(&mut self) {
+        self.selected = min(self.selected + 1, self.matches().len().saturating_sub(1));
+    }
+
+    pub fn move_selector_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// The command to run. A completion picked with the selector
+    /// replaces the typed command name
+    pub fn command(&self) -> Result<Command, Error> {
+        if self.selected == 0 {
+            return Command::parse(&self.input);
+        }
+
+        let args: Vec<&str> = self.input.split_whitespace().skip(1).collect();
+        match self.matches().get(self.selected) {
+            Some(spec) => Command::parse(&format!("{} {}", spec.name, args.join(" "))),
+            None => Command::parse(&self.input),
+        }
+    }
+
+    pub fn get_widget(&self, theme: &Theme) -> CommandPaletteWidget {
+        CommandPaletteWidget {
+            theme: *theme,
+            input: self.input.clone(),
+            matches: self.matches(),
+            selected: self.selected,
+        }
+    }
+}
+
+pub struct CommandPaletteWidget {
+    theme: Theme,
+    input: String,
+    matches: Vec<CommandSpec>,
+    selected: usize,
+}
+
+impl Widget for CommandPaletteWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for x in area.left()..area.right() {
+            for y in area.top()..area.bottom() {
+                buf.get_mut(x, y).reset();
+            }
+        }
+
+        let block = bordered_box(&self.theme, Some("Command".to_string()));
+        let inner_area = block.inner(area);
+        block.render(area, buf);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(inner_area);
+
+        Paragraph::new(Spans::from(vec![
+            Span::raw(format!(":{}", self.input)),
+            Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+        ]))
+        .style(self.theme.text_style())
+        .render(chunks[0], buf);
+
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|spec| {
+                ListItem::new(Spans::from(vec![
+                    Span::raw(format!("{} {}", spec.name, spec.args)),
+                    Span::styled(
+                        format!("  {}", spec.description),
+                        Style::default().fg(self.theme.muted),
+                    ),
+                ]))
+            })
+            .collect();
+        let mut state = ListState::default();
+        state.select(Some(self.selected));
+
+        StatefulWidget::render(
+            List::new(items)
+                .style(self.theme.text_style())
+                .highlight_style(self.theme.highlight_style())
+                .highlight_symbol("->"),
+            chunks[1],
+            buf,
+            &mut state,
+        );
+    }
+}
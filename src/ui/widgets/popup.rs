@@ -1,16 +1,20 @@
 use std::cmp::min;
 
 use crate::bluetooth::agent::BluezError;
+use btleplug::platform::PeripheralId;
 use crossterm::event::KeyCode;
 use tokio::sync::oneshot::Sender;
 use tui::{
     buffer::Buffer,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    style::{Modifier, Style},
+    text::{Span, Spans},
     widgets::{List, ListItem, ListState, Paragraph, StatefulWidget, Widget},
 };
 
-use super::{statics::blue_box, text_style};
+use crate::ui::theme::Theme;
+
+use super::statics::bordered_box;
 
 pub trait Popup {
     fn confirm(&self);
@@ -53,8 +57,25 @@ impl YesNoPopup {
         }
     }
 
-    pub fn get_widget(&self) -> YesNoPopupWidget {
+    /// Index of the option under the mouse, 0 for yes and 1 for no,
+    /// when the popup is drawn in `area`
+    pub fn option_at(&self, area: Rect, column: u16, row: u16) -> Option<usize> {
+        let options = options_area(area);
+        let rows = options.top()..min(options.top() + 2, options.bottom());
+        if column >= options.left() && column < options.right() && rows.contains(&row) {
+            Some((row - options.top()) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.state.select(Some(min(index, 1)));
+    }
+
+    pub fn get_widget(&self, theme: &Theme) -> YesNoPopupWidget {
         YesNoPopupWidget {
+            theme: *theme,
             question: self.question.clone(),
             state: self.state.clone(),
         }
@@ -72,13 +93,36 @@ impl YesNoPopup {
             Err(BluezError::Canceled("canceled".to_string()))
         };
 
-        let tx = self.responder.take().unwrap();
+        // BlueZ may have cancelled the request already
+        if let Some(tx) = self.responder.take() {
+            let _ = tx.send(result);
+        }
+    }
 
-        tx.send(result).unwrap();
+    pub fn cancel(&mut self) {
+        if let Some(tx) = self.responder.take() {
+            let _ = tx.send(Err(BluezError::Canceled("canceled".to_string())));
+        }
     }
 }
 
+/// Area of the question inside the popup and of the yes/no options
+fn popup_chunks(area: Rect) -> Vec<Rect> {
+    Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .split(area.inner(&Margin {
+            vertical: 1,
+            horizontal: 1,
+        }))
+}
+
+fn options_area(area: Rect) -> Rect {
+    popup_chunks(area)[1]
+}
+
 pub struct YesNoPopupWidget {
+    theme: Theme,
     question: String,
     state: ListState,
 }
@@ -91,31 +135,19 @@ impl Widget for YesNoPopupWidget {
             }
         }
 
-        let block = Some(blue_box(None));
-
-        let question_area = match block {
-            Some(b) => {
-                let inner_area = b.inner(area);
-                b.render(area, buf);
-                inner_area
-            }
-            None => area,
-        };
+        bordered_box(&self.theme, None).render(area, buf);
 
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
-            .split(question_area);
+        let chunks = popup_chunks(area);
 
         Paragraph::new(self.question)
-            .style(text_style())
+            .style(self.theme.text_style())
             .alignment(Alignment::Center)
             .render(chunks[0], buf);
 
         StatefulWidget::render(
             List::new(vec![ListItem::new("Yes"), ListItem::new("No")])
-                .style(text_style())
-                .highlight_style(Style::default().bg(Color::White).fg(Color::Black))
+                .style(self.theme.text_style())
+                .highlight_style(self.theme.highlight_style())
                 .highlight_symbol("->"),
             chunks[1],
             buf,
@@ -124,6 +156,78 @@ impl Widget for YesNoPopupWidget {
     }
 }
 
+/// What the text typed in an [`InputPopup`] is used for
+#[derive(Debug, Clone)]
+pub enum InputPurpose {
+    Rename {
+        periph_id: PeripheralId,
+        address: String,
+    },
+}
+
+pub struct InputPopup {
+    pub purpose: InputPurpose,
+    title: String,
+    value: String,
+}
+
+impl InputPopup {
+    pub fn new(purpose: InputPurpose, title: String, value: String) -> InputPopup {
+        InputPopup {
+            purpose,
+            title,
+            value,
+        }
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.value.push(c);
+    }
+
+    pub fn pop(&mut self) {
+        self.value.pop();
+    }
+
+    pub fn value(&self) -> &str {
+        self.value.trim()
+    }
+
+    pub fn get_widget(&self, theme: &Theme) -> InputPopupWidget {
+        InputPopupWidget {
+            theme: *theme,
+            title: self.title.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+pub struct InputPopupWidget {
+    theme: Theme,
+    title: String,
+    value: String,
+}
+
+impl Widget for InputPopupWidget {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for x in area.left()..area.right() {
+            for y in area.top()..area.bottom() {
+                buf.get_mut(x, y).reset();
+            }
+        }
+
+        let block = bordered_box(&self.theme, Some(self.title));
+        let inner_area = block.inner(area);
+        block.render(area, buf);
+
+        Paragraph::new(Spans::from(vec![
+            Span::raw(self.value),
+            Span::styled("_", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+        ]))
+        .style(self.theme.text_style())
+        .render(inner_area, buf);
+    }
+}
+
 // impl Popup for YesNoPopup {}
 
 // impl Widget for YesNoPopup {}
@@ -137,3 +241,21 @@ impl Widget for YesNoPopupWidget {
 //     question: String,
 //     passkey: String,
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_option_at() {
+        let (tx, _rx) = tokio::sync::oneshot::channel();
+        let popup = YesNoPopup::new("Pair ?".to_string(), tx);
+        let area = Rect::new(10, 10, 40, 6);
+
+        assert_eq!(popup.option_at(area, 20, 12), Some(0));
+        assert_eq!(popup.option_at(area, 20, 13), Some(1));
+        assert_eq!(popup.option_at(area, 20, 11), None);
+        assert_eq!(popup.option_at(area, 20, 14), None);
+        assert_eq!(popup.option_at(area, 10, 12), None);
+    }
+}
@@ -1,56 +1,159 @@
 use tui::{
     layout::Alignment,
-    style::{Color, Style},
+    style::Style,
     text::{Span, Spans},
     widgets::{Block, Borders, Paragraph},
 };
 
-use super::text_style;
+use crate::{
+    keymap::{Action, Keymap},
+    ui::theme::Theme,
+};
 
-pub fn blue_box(title: Option<String>) -> Block<'static> {
+pub fn bordered_box(theme: &Theme, title: Option<String>) -> Block<'static> {
     let block = Block::default()
-        .style(Style::default().fg(Color::Black))
         .borders(Borders::all())
-        .border_style(Style::default().fg(Color::Blue));
+        .border_style(Style::default().fg(theme.border));
 
     if let Some(title_str) = title {
-        block.title(Span::styled(title_str, Style::default().fg(Color::White)))
+        block.title(Span::styled(title_str, Style::default().fg(theme.title)))
     } else {
         block
     }
 }
 
-pub fn main_commands<'a>(scanning: bool) -> Paragraph<'a> {
-    Paragraph::new(Spans::from(vec![
-        Span::raw("⇵: move through devices   "),
-        Span::raw(format!(
-            "s: {}   ",
-            if scanning {
-                "stop scanning"
-            } else {
-                "start scanning"
-            }
-        )),
-        Span::raw("c: connect   "),
-        Span::raw("d: disconnect   "),
-        Span::raw("q: quit"),
-    ]))
-    .style(text_style())
-    .block(blue_box(None))
-}
-
-pub fn popup_commands<'a>() -> Paragraph<'a> {
-    Paragraph::new(Spans::from(vec![
-        Span::raw("⇵: move   "),
-        Span::raw("↲: confirm"),
-    ]))
-    .style(text_style())
-    .block(blue_box(None))
-}
-
-pub fn title<'a>() -> Paragraph<'a> {
+static COMMANDS_SEPARATOR: &str = "   ";
+
+/// Command bar of `width` columns, with the commands that fit whole
+fn command_bar<'a>(theme: &Theme, commands: Vec<String>, width: u16) -> Paragraph<'a> {
+    // Inside the borders
+    let width = width.saturating_sub(2) as usize;
+    let mut shown: Vec<String> = Vec::new();
+    let mut used = 0;
+    for command in commands {
+        let command_width = Span::raw(command.as_str()).width();
+        let needed = if shown.is_empty() {
+            command_width
+        } else {
+            COMMANDS_SEPARATOR.len() + command_width
+        };
+        if used + needed > width {
+            break;
+        }
+        used += needed;
+        shown.push(command);
+    }
+
+    Paragraph::new(Spans::from(Span::raw(shown.join(COMMANDS_SEPARATOR))))
+        .style(theme.text_style())
+        .block(bordered_box(theme, None))
+}
+
+/// Command bar listing the keys currently bound to each action
+fn commands<'a>(
+    theme: &Theme,
+    keymap: &Keymap,
+    width: u16,
+    actions: &[(Action, &str)],
+) -> Paragraph<'a> {
+    let commands: Vec<String> = actions
+        .iter()
+        .filter(|(action, _)| !keymap.keys(*action).is_empty())
+        .map(|(action, description)| format!("{}: {}", keymap.keys_str(*action), description))
+        .collect();
+
+    command_bar(theme, commands, width)
+}
+
+pub fn main_commands<'a>(
+    theme: &Theme,
+    scanning: bool,
+    keymap: &Keymap,
+    width: u16,
+) -> Paragraph<'a> {
+    commands(
+        theme,
+        keymap,
+        width,
+        &[
+            (Action::MoveUp, "up"),
+            (Action::MoveDown, "down"),
+            (
+                Action::Scan,
+                if scanning {
+                    "stop scanning"
+                } else {
+                    "start scanning"
+                },
+            ),
+            (Action::Connect, Action::Connect.description()),
+            (Action::Disconnect, Action::Disconnect.description()),
+            (Action::Help, "help"),
+            (Action::Palette, "commands"),
+            (Action::Quit, Action::Quit.description()),
+        ],
+    )
+}
+
+pub fn logger_commands<'a>(theme: &Theme, keymap: &Keymap, width: u16) -> Paragraph<'a> {
+    commands(
+        theme,
+        keymap,
+        width,
+        &[
+            (Action::MoveUp, "up"),
+            (Action::MoveDown, "down"),
+            (Action::LogShowLess, "show less"),
+            (Action::LogShowMore, "show more"),
+            (Action::LogPageUp, "page up"),
+            (Action::LogPageDown, "page down"),
+            (Action::LogFullscreen, "full screen"),
+            (Action::Cancel, "leave"),
+        ],
+    )
+}
+
+pub fn popup_commands<'a>(theme: &Theme, keymap: &Keymap, width: u16) -> Paragraph<'a> {
+    commands(
+        theme,
+        keymap,
+        width,
+        &[
+            (Action::MoveUp, "up"),
+            (Action::MoveDown, "down"),
+            (Action::Confirm, Action::Confirm.description()),
+            (Action::Cancel, Action::Cancel.description()),
+        ],
+    )
+}
+
+pub fn input_commands<'a>(theme: &Theme, keymap: &Keymap, width: u16) -> Paragraph<'a> {
+    commands(
+        theme,
+        keymap,
+        width,
+        &[
+            (Action::Confirm, Action::Confirm.description()),
+            (Action::Cancel, Action::Cancel.description()),
+        ],
+    )
+}
+
+pub fn palette_commands<'a>(theme: &Theme, keymap: &Keymap, width: u16) -> Paragraph<'a> {
+    command_bar(
+        theme,
+        vec![
+            "↑/↓: select".to_string(),
+            format!("{}: run", keymap.keys_str(Action::Confirm)),
+            format!("{}: cancel", keymap.keys_str(Action::Cancel)),
+        ],
+        width,
+    )
+}
+
+pub fn title<'a>(theme: &Theme) -> Paragraph<'a> {
     Paragraph::new("bltui")
-        .style(Style::default().fg(Color::White))
+        .style(Style::default().fg(theme.title))
         .alignment(Alignment::Center)
-        .block(blue_box(None))
+        .block(bordered_box(theme, None))
 }
@@ -0,0 +1,212 @@
+use std::{
+    cmp::min,
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    text::Span,
+    widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap},
+};
+
+use crate::ui::theme::Theme;
+
+/// How long a toast stays on screen
+static TOAST_DURATION: Duration = Duration::from_secs(5);
+/// Toasts shown at once, the oldest one is dropped for a new one
+pub const MAX_TOASTS: usize = 3;
+static TOAST_WIDTH: u16 = 50;
+/// Lines of a message shown before it is cut
+static MAX_LINES: u16 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastLevel {
+    Success,
+    Warning,
+    Error,
+}
+
+impl ToastLevel {
+    fn title(&self) -> &'static str {
+        match self {
+            ToastLevel::Success => "Done",
+            ToastLevel::Warning => "Warning",
+            ToastLevel::Error => "Error",
+        }
+    }
+
+    fn color(&self, theme: &Theme) -> Color {
+        match self {
+            ToastLevel::Success => theme.success,
+            ToastLevel::Warning => theme.warning,
+            ToastLevel::Error => theme.error,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Toast {
+    level: ToastLevel,
+    message: String,
+    expires: Instant,
+}
+
+impl Toast {
+    /// Height of the toast with its borders, for a given width
+    fn height(&self, width: u16) -> u16 {
+        let inner_width = width.saturating_sub(2).max(1) as usize;
+        let lines = self.message.chars().count().div_ceil(inner_width);
+        lines.clamp(1, MAX_LINES as usize) as u16 + 2
+    }
+}
+
+/// Notifications shown over the panes for a few seconds
+#[derive(Debug, Default)]
+pub struct Toasts {
+    toasts: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub fn success(&mut self, message: String) {
+        self.push_at(Instant::now(), ToastLevel::Success, message);
+    }
+
+    pub fn warning(&mut self, message: String) {
+        self.push_at(Instant::now(), ToastLevel::Warning, message);
+    }
+
+    pub fn error(&mut self, message: String) {
+        self.push_at(Instant::now(), ToastLevel::Error, message);
+    }
+
+    fn push_at(&mut self, now: Instant, level: ToastLevel, message: String) {
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back(Toast {
+            level,
+            message,
+            expires: now + TOAST_DURATION,
+        });
+    }
+
+    /// Remove the toasts shown long enough, returning whether there
+    /// were any
+    pub fn expire(&mut self) -> bool {
+        self.expire_at(Instant::now())
+    }
+
+    fn expire_at(&mut self, now: Instant) -> bool {
+        let count = self.toasts.len();
+        self.toasts.retain(|toast| toast.expires > now);
+        self.toasts.len() != count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.toasts.is_empty()
+    }
+
+    /// Area taken by the toasts, in the bottom right corner of `area`
+    pub fn area(&self, area: Rect) -> Rect {
+        let width = min(TOAST_WIDTH, area.width);
+        let height = min(
+            self.toasts.iter().map(|toast| toast.height(width)).sum(),
+            area.height,
+        );
+        Rect::new(area.right() - width, area.bottom() - height, width, height)
+    }
+
+    pub fn get_widget(&self, theme: &Theme) -> ToastsWidget<'_> {
+        ToastsWidget {
+            theme: *theme,
+            toasts: self,
+        }
+    }
+}
+
+pub struct ToastsWidget<'a> {
+    theme: Theme,
+    toasts: &'a Toasts,
+}
+
+impl<'a> Widget for ToastsWidget<'a> {
+    /// Newest toast at the bottom
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut bottom = area.bottom();
+        for toast in self.toasts.toasts.iter().rev() {
+            let height = min(toast.height(area.width), bottom - area.top());
+            if height < 3 {
+                break;
+            }
+            bottom -= height;
+            let toast_area = Rect::new(area.x, bottom, area.width, height);
+
+            let color = toast.level.color(&self.theme);
+            Clear.render(toast_area, buf);
+            Paragraph::new(toast.message.as_str())
+                .style(self.theme.text_style())
+                .wrap(Wrap { trim: true })
+                .block(
+                    Block::default()
+                        .borders(Borders::all())
+                        .border_style(Style::default().fg(color))
+                        .title(Span::styled(
+                            toast.level.title(),
+                            Style::default().fg(color),
+                        )),
+                )
+                .render(toast_area, buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toasts_expire() {
+        let mut toasts = Toasts::default();
+        let now = Instant::now();
+        toasts.push_at(now, ToastLevel::Success, "first".to_string());
+        toasts.push_at(
+            now + Duration::from_secs(2),
+            ToastLevel::Warning,
+            "second".to_string(),
+        );
+
+        assert!(!toasts.expire_at(now + Duration::from_secs(1)));
+        assert!(toasts.expire_at(now + TOAST_DURATION));
+        assert_eq!(toasts.toasts.len(), 1);
+        assert!(toasts.expire_at(now + Duration::from_secs(10)));
+        assert!(toasts.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_toast_is_dropped() {
+        let mut toasts = Toasts::default();
+        for i in 0..=MAX_TOASTS {
+            toasts.error(format!("toast {}", i));
+        }
+
+        assert_eq!(toasts.toasts.len(), MAX_TOASTS);
+        assert_eq!(toasts.toasts[0].message, "toast 1");
+    }
+
+    #[test]
+    fn test_area() {
+        let mut toasts = Toasts::default();
+        toasts.success("short".to_string());
+        // Wraps on two lines of 48 characters
+        toasts.error("x".repeat(60));
+
+        let area = toasts.area(Rect::new(0, 3, 100, 34));
+        assert_eq!(area, Rect::new(50, 30, 50, 7));
+
+        let area = toasts.area(Rect::new(0, 3, 30, 5));
+        assert_eq!(area, Rect::new(0, 3, 30, 5));
+    }
+}
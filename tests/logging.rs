@@ -0,0 +1,58 @@
+//! The logger is global, so these tests run in their own process rather
+//! than next to the snapshots of the logger pane
+
+use std::path::PathBuf;
+
+use bltui::{
+    logging::{initialize_logging, set_level, LogFormat, LoggingConfig},
+    settings::LogSettings,
+};
+use log::{debug, info, LevelFilter};
+use tui::{backend::TestBackend, Terminal};
+use tui_logger::{TuiLoggerSmartWidget, TuiWidgetEvent, TuiWidgetState};
+
+/// Text of the logger pane, with its target selector
+fn logger_text(state: &TuiWidgetState) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(120, 10)).unwrap();
+    terminal
+        .draw(|frame| {
+            let widget = TuiLoggerSmartWidget::default().state(state);
+            frame.render_widget(widget, frame.size());
+        })
+        .unwrap();
+    let buffer = terminal.backend().buffer();
+    buffer
+        .content
+        .iter()
+        .map(|cell| cell.symbol.as_str())
+        .collect()
+}
+
+#[test]
+fn test_set_level_keeps_hidden_targets() {
+    initialize_logging(LogSettings {
+        level: LevelFilter::Info,
+        log_to_file: false,
+        folder: PathBuf::new(),
+        format: LogFormat::Text,
+        files: LoggingConfig::default(),
+    })
+    .unwrap();
+    let mut state = TuiWidgetState::new();
+    info!(target: "bltui::hidden_test", "Seen at info");
+    info!(target: "bltui::level_test", "Seen at info");
+    logger_text(&state);
+    // Hide the first target of the selector, from info down to off
+    for _ in 0..3 {
+        state.transition(&TuiWidgetEvent::LeftKey);
+        logger_text(&state);
+    }
+
+    set_level(LevelFilter::Debug, &mut state);
+    debug!(target: "bltui::hidden_test", "Hidden at debug");
+    debug!(target: "bltui::level_test", "Shown at debug");
+
+    let text = logger_text(&state);
+    assert!(text.contains("Shown at debug"));
+    assert!(!text.contains("Hidden at debug"));
+}